@@ -5,11 +5,18 @@ use regex::Regex;
 // TempDir uses https://docs.rs/fastrand/latest/fastrand/struct.Rng.html#method.alphanumeric
 // This regex only matches on unix paths,
 // will need to do something else if anyone ever runs these tests on Windows.
+// Paths end at whitespace or quotes, so that several paths on a line are redacted separately.
 static TMP_DIR_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"/.*?\.tmp[a-zA-Z0-9]{6}").unwrap());
+    LazyLock::new(|| Regex::new(r#"/[^\s"]*?\.tmp[a-zA-Z0-9]{6}"#).unwrap());
+
+// Sixel images are `DCS ... q <data> ST`, their pixels depend on the fonts at hand,
+// so tests only see that an image was shown, one per line.
+static SIXEL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1bP[^\x1b]*q[^\x1b]*\x1b\\\n?").unwrap());
 
 pub fn redacted_text(out: &str) -> String {
-    TMP_DIR_RE.replace_all(out, "[TMP_DIR]").to_string()
+    let out = SIXEL_RE.replace_all(out, "[SIXEL]\n");
+    TMP_DIR_RE.replace_all(&out, "[TMP_DIR]").to_string()
 }
 
 pub fn format_diff(chunks: Vec<dissimilar::Chunk>) -> String {
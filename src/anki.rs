@@ -0,0 +1,1141 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::LazyLock;
+
+use chrono::DateTime;
+use chrono::TimeDelta;
+use chrono::Utc;
+use percent_encoding::AsciiSet;
+use percent_encoding::CONTROLS;
+use percent_encoding::utf8_percent_encode;
+use regex::Regex;
+use rs_fsrs::Rating;
+use rs_fsrs::State;
+use rusqlite::Connection;
+use rusqlite::params;
+use serde_json::json;
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+use crate::assets::AssetLookup;
+use crate::assets::find_asset;
+use crate::assets::rewrite_urls;
+use crate::error::Error;
+use crate::error::PathContext;
+use crate::error::RenderTool;
+use crate::error::Result;
+use crate::review::replay_reviews;
+use crate::storage::StorageManager;
+use crate::types::Card;
+use crate::types::CardBody;
+use crate::types::CardMetadata;
+use crate::types::CardRef;
+use crate::types::CardReviewLog;
+use crate::types::FSRSMeta;
+use crate::types::ReviewLog;
+use crate::types::SRSMeta;
+
+// Legacy collection schema (version 11), every Anki version can import it.
+// Based on rslib/src/storage/schema11.sql in the Anki repository.
+const COLLECTION_SCHEMA: &str = "
+create table col (
+    id integer primary key, crt integer not null, mod integer not null,
+    scm integer not null, ver integer not null, dty integer not null,
+    usn integer not null, ls integer not null, conf text not null,
+    models text not null, decks text not null, dconf text not null, tags text not null
+);
+create table notes (
+    id integer primary key, guid text not null, mid integer not null,
+    mod integer not null, usn integer not null, tags text not null,
+    flds text not null, sfld integer not null, csum integer not null,
+    flags integer not null, data text not null
+);
+create table cards (
+    id integer primary key, nid integer not null, did integer not null,
+    ord integer not null, mod integer not null, usn integer not null,
+    type integer not null, queue integer not null, due integer not null,
+    ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null,
+    odid integer not null, flags integer not null, data text not null
+);
+create table revlog (
+    id integer primary key, cid integer not null, usn integer not null,
+    ease integer not null, ivl integer not null, lastIvl integer not null,
+    factor integer not null, time integer not null, type integer not null
+);
+create table graves (usn integer not null, oid integer not null, type integer not null);
+create index ix_notes_usn on notes (usn);
+create index ix_cards_usn on cards (usn);
+create index ix_revlog_usn on revlog (usn);
+create index ix_cards_nid on cards (nid);
+create index ix_cards_sched on cards (did, queue, due);
+create index ix_revlog_cid on revlog (cid);
+create index ix_notes_csum on notes (csum);
+";
+
+// Fixed, so that repeated exports reuse the same note type in Anki.
+const NOTE_TYPE_ID: i64 = 1_735_689_600_000;
+const NOTE_TYPE_NAME: &str = "Basic (losrs)";
+const DEFAULT_DECK_ID: i64 = 1;
+const DEFAULT_DECK_CONF_ID: i64 = 1;
+// Anki's default ease, FSRS does not use it but Anki expects it on reviewed cards.
+const DEFAULT_FACTOR: i64 = 2500;
+// Anki separates note fields with the unit separator.
+const FIELD_SEPARATOR: &str = "\x1f";
+
+// Card type and queue values, see rslib/src/card/mod.rs in the Anki repository.
+const CARD_TYPE_NEW: i64 = 0;
+const CARD_TYPE_LEARN: i64 = 1;
+const CARD_TYPE_REVIEW: i64 = 2;
+const CARD_TYPE_RELEARN: i64 = 3;
+const QUEUE_NEW: i64 = 0;
+const QUEUE_LEARN: i64 = 1;
+const QUEUE_REVIEW: i64 = 2;
+
+// Review log kinds, see rslib/src/revlog/mod.rs in the Anki repository.
+const REVLOG_LEARN: i64 = 0;
+const REVLOG_REVIEW: i64 = 1;
+const REVLOG_RELEARN: i64 = 2;
+
+pub struct AnkiExportSummary {
+    pub cards: usize,
+    pub decks: usize,
+    pub media_files: usize,
+}
+
+// Files referenced by cards, stored in the package as "0", "1", ...
+// and referred to by their (unique) file names from the notes.
+#[derive(Default)]
+struct MediaFiles {
+    names_by_path: HashMap<PathBuf, String>,
+    names: HashSet<String>,
+    files: Vec<(String, PathBuf)>,
+}
+
+impl MediaFiles {
+    fn add(&mut self, path: PathBuf) -> String {
+        if let Some(name) = self.names_by_path.get(&path) {
+            return name.clone();
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let mut name = file_name.clone();
+        let mut n = 1;
+        while self.names.contains(&name) {
+            name = format!("{}_{}", n, file_name);
+            n += 1;
+        }
+        self.names.insert(name.clone());
+        self.names_by_path.insert(path.clone(), name.clone());
+        self.files.push((name.clone(), path));
+        name
+    }
+}
+
+fn markdown_error(message: String) -> Error {
+    Error::Render { tool: RenderTool::Markdown, message }
+}
+
+// Points references to local files at their media names, registering them as media.
+fn rewrite_media_references(
+    markdown: &str,
+    page_path: &Path,
+    media_files: &mut MediaFiles,
+) -> Result<String> {
+    let page_dir = page_path.parent().unwrap_or(Path::new(""));
+    let graph_root = page_dir.parent().unwrap_or(page_dir);
+    rewrite_urls(markdown, true, |url| {
+        Ok(match find_asset(url, page_dir, graph_root) {
+            AssetLookup::Found(path) => Some(media_files.add(path)),
+            AssetLookup::NotAsset | AssetLookup::Missing(_) => None,
+        })
+    })
+}
+
+fn markdown_to_html(markdown: &str) -> Result<String> {
+    markdown::to_html_with_options(markdown, &markdown::Options::gfm())
+        .map_err(|x| markdown_error(format!("could not convert markdown to html: {:?}", x)))
+}
+
+fn card_to_fields(card: &Card, media_files: &mut MediaFiles) -> Result<(String, String)> {
+    let page_path = card.metadata.card_ref.source_path.as_path();
+    let prompt = rewrite_media_references(&card.body.prompt_text(), page_path, media_files)?;
+    let response = rewrite_media_references(&card.body.response_text(), page_path, media_files)?;
+
+    Ok((markdown_to_html(&prompt)?, markdown_to_html(&response)?))
+}
+
+// Logseq namespaces are stored as `a___b.md` (or `a%2Fb.md` in older graphs),
+// they map naturally to nested decks.
+fn page_deck_name(deck: &str, page_path: &Path) -> String {
+    let page_name = page_path.file_stem().unwrap_or_default().to_string_lossy();
+    format!("{}::{}", deck, page_name.replace("___", "::").replace("%2F", "::"))
+}
+
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.trim().to_owned()
+}
+
+// Anki uses the checksum of the sort field to find duplicates.
+fn field_checksum(sort_field: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(sort_field).digest().bytes();
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+// Stable across exports, so that importing again updates notes instead of duplicating them.
+fn note_guid(card_metadata: &CardMetadata) -> String {
+    match card_metadata.card_ref.serial_num {
+        Some(csn) => format!("losrs-csn-{}", csn),
+        None => format!("losrs-{}", card_metadata.card_ref.prompt_fingerprint),
+    }
+}
+
+struct AnkiScheduling {
+    card_type: i64,
+    queue: i64,
+    due: i64,
+    ivl: i64,
+    factor: i64,
+    left: i64,
+    data: String,
+}
+
+// Review cards are due on a day number counted from the collection creation,
+// (re)learning cards are due at a unix timestamp, new cards are due at a position.
+fn to_anki_scheduling(
+    fsrs_meta: &FSRSMeta,
+    new_position: i64,
+    collection_created: DateTime<Utc>,
+) -> AnkiScheduling {
+    let memory_state = || {
+        // Anki keeps the FSRS memory state of a card in its data.
+        json!({
+            "s": (fsrs_meta.stability * 10000.0).round() / 10000.0,
+            "d": (fsrs_meta.difficulty * 1000.0).round() / 1000.0,
+        })
+        .to_string()
+    };
+    let ivl = fsrs_meta.scheduled_days.max(1);
+    match fsrs_meta.state {
+        State::New => AnkiScheduling {
+            card_type: CARD_TYPE_NEW,
+            queue: QUEUE_NEW,
+            due: new_position,
+            ivl: 0,
+            factor: 0,
+            left: 0,
+            data: String::new(),
+        },
+        State::Learning => AnkiScheduling {
+            card_type: CARD_TYPE_LEARN,
+            queue: QUEUE_LEARN,
+            due: fsrs_meta.due.timestamp(),
+            ivl: 0,
+            factor: DEFAULT_FACTOR,
+            left: 1001,
+            data: memory_state(),
+        },
+        State::Review => AnkiScheduling {
+            card_type: CARD_TYPE_REVIEW,
+            queue: QUEUE_REVIEW,
+            due: (fsrs_meta.due.date_naive() - collection_created.date_naive()).num_days(),
+            ivl,
+            factor: DEFAULT_FACTOR,
+            left: 0,
+            data: memory_state(),
+        },
+        State::Relearning => AnkiScheduling {
+            card_type: CARD_TYPE_RELEARN,
+            queue: QUEUE_LEARN,
+            due: fsrs_meta.due.timestamp(),
+            ivl,
+            factor: DEFAULT_FACTOR,
+            left: 1001,
+            data: memory_state(),
+        },
+    }
+}
+
+fn note_type_json(now: DateTime<Utc>) -> serde_json::Value {
+    let field = |name: &str, ord: u32| {
+        json!({
+            "name": name, "ord": ord, "sticky": false, "rtl": false,
+            "font": "Arial", "size": 20, "media": [],
+        })
+    };
+    json!({
+        NOTE_TYPE_ID.to_string(): {
+            "id": NOTE_TYPE_ID,
+            "name": NOTE_TYPE_NAME,
+            "type": 0,
+            "mod": now.timestamp(),
+            "usn": -1,
+            "sortf": 0,
+            "did": DEFAULT_DECK_ID,
+            "tmpls": [{
+                "name": "Card 1",
+                "ord": 0,
+                "qfmt": "{{Front}}",
+                "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}",
+                "bqfmt": "",
+                "bafmt": "",
+                "did": null,
+            }],
+            "flds": [field("Front", 0), field("Back", 1)],
+            "css": ".card {\n font-family: arial;\n font-size: 20px;\n text-align: left;\n color: black;\n background-color: white;\n}\n",
+            "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+            "latexPost": "\\end{document}",
+            "latexsvg": false,
+            "req": [[0, "any", [0]]],
+            "tags": [],
+            "vers": [],
+        }
+    })
+}
+
+fn deck_json(id: i64, name: &str, now: DateTime<Utc>) -> serde_json::Value {
+    json!({
+        "id": id,
+        "name": name,
+        "mod": now.timestamp(),
+        "usn": -1,
+        "lrnToday": [0, 0],
+        "revToday": [0, 0],
+        "newToday": [0, 0],
+        "timeToday": [0, 0],
+        "collapsed": false,
+        "browserCollapsed": false,
+        "desc": "",
+        "dyn": 0,
+        "conf": DEFAULT_DECK_CONF_ID,
+        "extendNew": 0,
+        "extendRev": 0,
+    })
+}
+
+fn deck_conf_json() -> serde_json::Value {
+    json!({
+        DEFAULT_DECK_CONF_ID.to_string(): {
+            "id": DEFAULT_DECK_CONF_ID,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1, 10], "ints": [1, 4, 0], "initialFactor": DEFAULT_FACTOR,
+                "order": 1, "perDay": 20, "bury": false,
+            },
+            "lapse": {
+                "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 1,
+            },
+            "rev": {
+                "perDay": 200, "ease4": 1.3, "maxIvl": 36500, "ivlFct": 1, "bury": false,
+                "hardFactor": 1.2,
+            },
+        }
+    })
+}
+
+fn collection_conf_json() -> serde_json::Value {
+    json!({
+        "nextPos": 1,
+        "estTimes": true,
+        "activeDecks": [DEFAULT_DECK_ID],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": DEFAULT_DECK_ID,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": NOTE_TYPE_ID,
+        "collapseTime": 1200,
+    })
+}
+
+fn revlog_kind(state: State) -> i64 {
+    match state {
+        State::New | State::Learning => REVLOG_LEARN,
+        State::Review => REVLOG_REVIEW,
+        State::Relearning => REVLOG_RELEARN,
+    }
+}
+
+fn insert_revlog(
+    conn: &Connection,
+    card_id: i64,
+    card_review_logs: &[&CardReviewLog],
+    fsrs_meta: &FSRSMeta,
+    used_revlog_ids: &mut HashSet<i64>,
+) -> Result<()> {
+    // The interval after a review is the interval before the next one,
+    // the last review resulted in the current interval of the card.
+    let next_intervals = card_review_logs
+        .iter()
+        .skip(1)
+        .map(|crl| crl.review_log.scheduled_days)
+        .chain([fsrs_meta.scheduled_days]);
+    for (crl, ivl) in card_review_logs.iter().zip(next_intervals) {
+        let review_log = &crl.review_log;
+        // Review log ids are the review timestamps in milliseconds and must be unique.
+        let mut id = review_log.reviewed_date.timestamp_millis();
+        while !used_revlog_ids.insert(id) {
+            id += 1;
+        }
+        conn.execute(
+            "insert into revlog values (?1, ?2, -1, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                id,
+                card_id,
+                review_log.rating as i64,
+                ivl,
+                review_log.scheduled_days,
+                if review_log.state == State::New { 0 } else { DEFAULT_FACTOR },
+                crl.review_duration_ms.unwrap_or_default() as i64,
+                revlog_kind(review_log.state),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn write_collection(
+    collection_path: &Path,
+    deck: &str,
+    cards_by_page: &BTreeMap<Arc<PathBuf>, Vec<Card>>,
+    card_review_logs: &[CardReviewLog],
+    media_files: &mut MediaFiles,
+) -> Result<()> {
+    let now = Utc::now();
+    // Review cards are due on days counted from the collection creation,
+    // start the collection early enough for all of them to be due on a non-negative day.
+    let collection_created = cards_by_page
+        .values()
+        .flatten()
+        .map(|c| &c.metadata.srs_meta.fsrs_meta)
+        .filter(|fm| fm.state == State::Review)
+        .map(|fm| fm.due)
+        .chain([now])
+        .min()
+        .unwrap_or(now);
+    let collection_created = collection_created
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+
+    let mut card_review_logs_by_csn: HashMap<u64, Vec<&CardReviewLog>> = HashMap::new();
+    for crl in card_review_logs {
+        card_review_logs_by_csn.entry(crl.serial_num).or_default().push(crl);
+    }
+
+    let conn = Connection::open(collection_path)?;
+    conn.execute_batch(COLLECTION_SCHEMA)?;
+
+    // Ids of notes, cards and decks are creation timestamps in milliseconds in Anki.
+    let mut next_id = now.timestamp_millis();
+    let mut next_id = || {
+        next_id += 1;
+        next_id
+    };
+
+    let mut deck_ids_by_name: BTreeMap<String, i64> = BTreeMap::new();
+    deck_ids_by_name.insert("Default".to_owned(), DEFAULT_DECK_ID);
+
+    let mut used_revlog_ids = HashSet::new();
+    let mut new_position = 0;
+    for (page_path, cards) in cards_by_page {
+        let deck_name = page_deck_name(deck, page_path);
+        // Parent decks of nested decks have to exist as well.
+        for (i, _) in deck_name.match_indices("::") {
+            deck_ids_by_name.entry(deck_name[..i].to_owned()).or_insert_with(&mut next_id);
+        }
+        let deck_id = *deck_ids_by_name.entry(deck_name).or_insert_with(&mut next_id);
+
+        for card in cards {
+            let (front, back) = card_to_fields(card, media_files)?;
+            let sort_field = strip_html(&front);
+            let note_id = next_id();
+            conn.execute(
+                "insert into notes values (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')",
+                params![
+                    note_id,
+                    note_guid(&card.metadata),
+                    NOTE_TYPE_ID,
+                    now.timestamp(),
+                    [front.as_str(), back.as_str()].join(FIELD_SEPARATOR),
+                    sort_field,
+                    field_checksum(&sort_field),
+                ],
+            )?;
+
+            let fsrs_meta = &card.metadata.srs_meta.fsrs_meta;
+            let scheduling = to_anki_scheduling(fsrs_meta, new_position, collection_created);
+            if fsrs_meta.state == State::New {
+                new_position += 1;
+            }
+            let card_id = next_id();
+            conn.execute(
+                "insert into cards values
+                 (?1, ?2, ?3, 0, ?4, -1, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 0, 0, 0, ?13)",
+                params![
+                    card_id,
+                    note_id,
+                    deck_id,
+                    now.timestamp(),
+                    scheduling.card_type,
+                    scheduling.queue,
+                    scheduling.due,
+                    scheduling.ivl,
+                    scheduling.factor,
+                    fsrs_meta.reps,
+                    fsrs_meta.lapses,
+                    scheduling.left,
+                    scheduling.data,
+                ],
+            )?;
+
+            let Some(csn) = card.metadata.card_ref.serial_num else { continue };
+            if let Some(crls) = card_review_logs_by_csn.get(&csn) {
+                insert_revlog(&conn, card_id, crls, fsrs_meta, &mut used_revlog_ids)?;
+            }
+        }
+    }
+
+    let decks: serde_json::Map<String, serde_json::Value> = deck_ids_by_name
+        .iter()
+        .map(|(name, id)| (id.to_string(), deck_json(*id, name, now)))
+        .collect();
+    conn.execute(
+        "insert into col values (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            collection_created.timestamp(),
+            now.timestamp_millis(),
+            collection_conf_json().to_string(),
+            note_type_json(now).to_string(),
+            serde_json::Value::Object(decks).to_string(),
+            deck_conf_json().to_string(),
+        ],
+    )?;
+    conn.close().map_err(|(_, err)| err)?;
+    Ok(())
+}
+
+pub fn export_anki(
+    storage_manager: &StorageManager,
+    path: &Path,
+    output: &Path,
+    deck: &str,
+) -> Result<AnkiExportSummary> {
+    let card_metas = storage_manager.select_card_metadata(path, None)?;
+    let mut cards_by_page: BTreeMap<Arc<PathBuf>, Vec<Card>> = BTreeMap::new();
+    for cm in card_metas {
+        let body = storage_manager.load_card_body_by_ref(&cm.card_ref)?;
+        let page_path = cm.card_ref.source_path.clone();
+        cards_by_page.entry(page_path).or_default().push(Card { metadata: cm, body });
+    }
+    let card_review_logs = storage_manager.load_review_logs()?.unwrap_or_default();
+
+    let mut media_files = MediaFiles::default();
+    let collection_file = NamedTempFile::new()?;
+    write_collection(
+        collection_file.path(),
+        deck,
+        &cards_by_page,
+        &card_review_logs,
+        &mut media_files,
+    )?;
+
+    let package_file = File::create(output).with_path(output)?;
+    let mut package = ZipWriter::new(package_file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    package.start_file("collection.anki2", options)?;
+    package.write_all(&fs::read(collection_file.path())?)?;
+
+    // Media files are stored under their index, the "media" file maps indexes to names.
+    let media_names: BTreeMap<String, &str> = media_files
+        .files
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (i.to_string(), name.as_str()))
+        .collect();
+    package.start_file("media", options)?;
+    package.write_all(serde_json::to_string(&media_names)?.as_bytes())?;
+    for (i, (_, media_path)) in media_files.files.iter().enumerate() {
+        package.start_file(i.to_string(), options)?;
+        package.write_all(&fs::read(media_path).with_path(media_path)?)?;
+    }
+    package.finish()?;
+
+    Ok(AnkiExportSummary {
+        cards: cards_by_page.values().map(Vec::len).sum(),
+        decks: cards_by_page.len(),
+        media_files: media_files.files.len(),
+    })
+}
+
+pub struct AnkiImportSummary {
+    pub cards: usize,
+    pub pages: usize,
+    pub media_files: usize,
+}
+
+// Note type kinds, see rslib/src/notetype/mod.rs in the Anki repository.
+const NOTE_TYPE_KIND_CLOZE: i64 = 1;
+
+static CLOZE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{c[0-9]+::(?<text>.*?)(::(?<hint>.*?))?\}\}").unwrap());
+
+const ASSET_NAME_ENCODE_SET: &AsciiSet =
+    &CONTROLS.add(b' ').add(b'%').add(b'(').add(b')').add(b'<').add(b'>');
+
+static TEXT_MARKUP_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\\\((?<inline>.*?)\\\)|\\\[(?<display>.*?)\\\]|\[sound:(?<sound>.+?)\]")
+        .unwrap()
+});
+
+struct AnkiNote {
+    fields: Vec<String>,
+    is_cloze: bool,
+}
+
+struct AnkiCard {
+    id: i64,
+    note_id: i64,
+    deck_id: i64,
+    card_type: i64,
+    due: i64,
+    ivl: i64,
+    reps: i32,
+    lapses: i32,
+    data: String,
+}
+
+struct AnkiReview {
+    reviewed_at: DateTime<Utc>,
+    rating: Rating,
+    duration_ms: u64,
+}
+
+fn decode_html_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| &rest[1..end]).filter(|e| e.len() <= 10);
+        let c = entity.and_then(|entity| match entity {
+            "nbsp" => Some(' '),
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            }
+        });
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Backslash escapes what markdown or Logseq would read as markup, like `*`, `#tags` or `[[links]]`,
+// and list markers at the start of a line.
+fn escape_markdown_text(text: &str, at_line_start: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let leading_digits = text.chars().take_while(char::is_ascii_digit).count();
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '$' | '~' => escaped.push('\\'),
+            '-' | '+' if i == 0 && at_line_start => escaped.push('\\'),
+            '.' | ')' if i == leading_digits && i > 0 && at_line_start => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn html_attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(|c: char| c.is_whitespace() || c == '/').next()?,
+    };
+    Some(decode_html_entities(value))
+}
+
+// Converts the (usually simple) HTML of Anki fields into lines of markdown.
+// Block elements and line breaks end lines, unknown elements are dropped keeping their text.
+struct HtmlToMarkdown<'a> {
+    asset_names: &'a HashMap<String, String>,
+    lines: Vec<String>,
+    line: String,
+    link_href: Option<String>,
+    skip_text: bool,
+}
+
+impl<'a> HtmlToMarkdown<'a> {
+    fn convert(html: &str, asset_names: &'a HashMap<String, String>) -> Vec<String> {
+        let mut converter = Self {
+            asset_names,
+            lines: Vec::new(),
+            line: String::new(),
+            link_href: None,
+            skip_text: false,
+        };
+        let mut rest = html;
+        while let Some(tag_start) = rest.find('<') {
+            converter.push_text(&rest[..tag_start]);
+            let Some(tag_len) = rest[tag_start..].find('>') else {
+                rest = &rest[tag_start..];
+                break;
+            };
+            converter.push_tag(&rest[tag_start + 1..tag_start + tag_len]);
+            rest = &rest[tag_start + tag_len + 1..];
+        }
+        converter.push_text(rest);
+        converter.end_line();
+        converter.lines
+    }
+
+    // Spaces and parentheses in names would end the URL in markdown, they are percent-encoded.
+    fn asset_url(&self, name: &str) -> String {
+        match self.asset_names.get(name) {
+            Some(asset_name) => {
+                format!("../assets/{}", utf8_percent_encode(asset_name, ASSET_NAME_ENCODE_SET))
+            }
+            None => name.to_owned(),
+        }
+    }
+
+    // Math and sounds become their markdown counterparts, any other text is kept literally.
+    fn push_text(&mut self, text: &str) {
+        if self.skip_text {
+            return;
+        }
+        let text = decode_html_entities(text);
+        let mut rest = text.as_str();
+        while let Some(caps) = TEXT_MARKUP_RE.captures(rest) {
+            let markup = caps.get(0).unwrap();
+            self.push_words(&rest[..markup.start()]);
+            // Anki uses MathJax delimiters, Logseq uses TeX ones.
+            if let Some(math) = caps.name("inline") {
+                self.line.push_str(&format!("${}$", math.as_str()));
+            } else if let Some(math) = caps.name("display") {
+                self.line.push_str(&format!("$${}$$", math.as_str()));
+            } else {
+                let name = &caps["sound"];
+                let url = self.asset_url(name);
+                self.line.push_str(&format!("[{}]({})", escape_markdown_text(name, false), url));
+            }
+            rest = &rest[markup.end()..];
+        }
+        self.push_words(rest);
+    }
+
+    // Collapses whitespace like browsers do.
+    fn push_words(&mut self, text: &str) {
+        for word in text.split_inclusive(char::is_whitespace) {
+            let word_trimmed = word.trim_end();
+            let at_line_start = self.line.trim().is_empty();
+            self.line.push_str(&escape_markdown_text(word_trimmed, at_line_start));
+            if word_trimmed.len() != word.len() && !self.line.ends_with(' ') {
+                self.line.push(' ');
+            }
+        }
+    }
+
+    fn push_tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "br" | "p" | "div" | "li" | "ul" | "ol" | "tr" | "blockquote" | "pre" | "hr" => {
+                self.end_line()
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.end_line(),
+            "b" | "strong" => self.line.push_str("**"),
+            "i" | "em" => self.line.push('*'),
+            "code" => self.line.push('`'),
+            "script" | "style" => self.skip_text = !closing,
+            "img" if !closing => {
+                if let Some(src) = html_attribute(tag, "src") {
+                    let alt = escape_markdown_text(
+                        &html_attribute(tag, "alt").unwrap_or_default(),
+                        false,
+                    );
+                    let url = self.asset_url(&src);
+                    self.line.push_str(&format!("![{}]({})", alt, url));
+                }
+            }
+            "a" if !closing => {
+                self.link_href = html_attribute(tag, "href");
+                if self.link_href.is_some() {
+                    self.line.push('[');
+                }
+            }
+            "a" => {
+                if let Some(href) = self.link_href.take() {
+                    self.line.push_str(&format!("]({})", href));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_line(&mut self) {
+        let line = self.line.trim();
+        if !line.is_empty() {
+            self.lines.push(line.to_owned());
+        }
+        self.line.clear();
+    }
+}
+
+fn note_to_card_body(note: &AnkiNote, asset_names: &HashMap<String, String>) -> CardBody {
+    let (prompt_html, response_html) = if note.is_cloze {
+        // Everything is asked at once, as we only keep one card per note.
+        let text = note.fields.first().map(String::as_str).unwrap_or_default();
+        let prompt = CLOZE_RE.replace_all(text, |caps: &regex::Captures| {
+            format!("[{}]", caps.name("hint").map_or("...", |m| m.as_str()))
+        });
+        let response = CLOZE_RE.replace_all(text, "$text");
+        let extra = note.fields.iter().skip(1).cloned();
+        (prompt.into_owned(), [response.into_owned()].into_iter().chain(extra).collect())
+    } else {
+        let prompt = note.fields.first().cloned().unwrap_or_default();
+        (prompt, note.fields.iter().skip(1).cloned().collect::<Vec<_>>())
+    };
+
+    let prompt = HtmlToMarkdown::convert(&prompt_html, asset_names).join("\n");
+    let response = response_html
+        .iter()
+        .flat_map(|html| HtmlToMarkdown::convert(html, asset_names))
+        .collect::<Vec<_>>()
+        .join("\n");
+    CardBody::from_text(&prompt, &response)
+}
+
+// Notes with several cards (like reversed ones) become a single card,
+// scheduled like the first of them.
+fn compute_fsrs_meta(
+    anki_card: &AnkiCard,
+    anki_reviews: &[AnkiReview],
+    collection_created: DateTime<Utc>,
+) -> (FSRSMeta, Vec<ReviewLog>) {
+    if !anki_reviews.is_empty() {
+        let reviews: Vec<(DateTime<Utc>, Rating)> =
+            anki_reviews.iter().map(|ar| (ar.reviewed_at, ar.rating)).collect();
+        return replay_reviews(&reviews);
+    }
+    if anki_card.card_type != CARD_TYPE_REVIEW {
+        return (FSRSMeta::default(), Vec::new());
+    }
+    // Without history, fall back to the current schedule and the memory state if there is one.
+    let memory_state: serde_json::Value = serde_json::from_str(&anki_card.data).unwrap_or_default();
+    let due = collection_created + TimeDelta::days(anki_card.due);
+    let fsrs_meta = FSRSMeta {
+        due,
+        stability: memory_state["s"].as_f64().unwrap_or(anki_card.ivl as f64),
+        difficulty: memory_state["d"].as_f64().unwrap_or(5.0),
+        elapsed_days: 0,
+        scheduled_days: anki_card.ivl,
+        reps: anki_card.reps,
+        lapses: anki_card.lapses,
+        state: State::Review,
+        last_review: due - TimeDelta::days(anki_card.ivl),
+    };
+    (fsrs_meta, Vec::new())
+}
+
+fn rating_from_ease(ease: i64) -> Option<Rating> {
+    match ease {
+        1 => Some(Rating::Again),
+        2 => Some(Rating::Hard),
+        3 => Some(Rating::Good),
+        4 => Some(Rating::Easy),
+        // Manual rescheduling is logged with ease 0
+        _ => None,
+    }
+}
+
+fn read_deck_names(conn: &Connection, decks_raw: &str) -> Result<HashMap<i64, String>> {
+    let decks: HashMap<String, serde_json::Value> = serde_json::from_str(decks_raw)?;
+    if !decks.is_empty() {
+        return Ok(decks
+            .values()
+            .filter_map(|d| Some((d["id"].as_i64()?, d["name"].as_str()?.to_owned())))
+            .collect());
+    }
+    // Newer schemas keep decks in their own table, with components separated by \x1f.
+    let mut stmt = conn.prepare("select id, name from decks")?;
+    let deck_names = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?.replace('\x1f', "::"))))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(deck_names)
+}
+
+fn read_cloze_note_type_ids(models_raw: &str) -> Result<HashSet<i64>> {
+    let models: HashMap<String, serde_json::Value> = serde_json::from_str(models_raw)?;
+    Ok(models
+        .values()
+        .filter(|m| m["type"].as_i64() == Some(NOTE_TYPE_KIND_CLOZE))
+        .filter_map(|m| m["id"].as_i64())
+        .collect())
+}
+
+fn page_file_name(deck_name: &str) -> String {
+    format!("{}.md", deck_name.replace("::", "___").replace('/', "___"))
+}
+
+// Media names come from the package, anything but a file name could write outside of assets.
+fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    let single_normal =
+        matches!((components.next(), components.next()), (Some(Component::Normal(_)), None));
+    single_normal && !name.contains(['/', '\\'])
+}
+
+// Copies media into assets, renaming files that would overwrite different assets.
+// Returns the asset names by media names.
+fn import_media(
+    package: &Path,
+    archive: &mut ZipArchive<File>,
+    assets_dir: &Path,
+) -> Result<HashMap<String, String>> {
+    let invalid_package =
+        |message: String| Error::InvalidFile { path: package.to_path_buf(), message };
+    let media_names: HashMap<String, String> = match archive.by_name("media") {
+        Ok(mut media_file) => {
+            let mut media_raw = String::new();
+            media_file.read_to_string(&mut media_raw)?;
+            serde_json::from_str(&media_raw)
+                .map_err(|e| invalid_package(format!("invalid media list: {}", e)))?
+        }
+        Err(zip::result::ZipError::FileNotFound) => HashMap::new(),
+        Err(err) => Err(err)?,
+    };
+    if media_names.is_empty() {
+        return Ok(HashMap::new());
+    }
+    if let Some(media_name) = media_names.values().find(|name| !is_plain_file_name(name)) {
+        return Err(invalid_package(format!(
+            "media file name {:?} is not a plain file name",
+            media_name
+        )));
+    }
+    fs::create_dir_all(assets_dir).with_path(assets_dir)?;
+
+    let mut asset_names = HashMap::new();
+    for (index, media_name) in media_names {
+        let mut content = Vec::new();
+        archive.by_name(&index)?.read_to_end(&mut content)?;
+
+        let mut asset_name = media_name.clone();
+        let mut n = 1;
+        while let Ok(existing) = fs::read(assets_dir.join(&asset_name)) {
+            if existing == content {
+                break;
+            }
+            asset_name = format!("{}_{}", n, media_name);
+            n += 1;
+        }
+        let asset_path = assets_dir.join(&asset_name);
+        fs::write(&asset_path, content).with_path(&asset_path)?;
+        asset_names.insert(media_name, asset_name);
+    }
+    Ok(asset_names)
+}
+
+pub fn import_anki(
+    storage_manager: &mut StorageManager,
+    package: &Path,
+    graph_root: &Path,
+) -> Result<AnkiImportSummary> {
+    let invalid_package =
+        |message: String| Error::InvalidFile { path: package.to_path_buf(), message };
+    let package_file = File::open(package).with_path(package)?;
+    let mut archive = ZipArchive::new(package_file)?;
+
+    // Packages exported by recent Anki versions only contain a placeholder collection.anki2.
+    if archive.index_for_name("collection.anki21b").is_some() {
+        return Err(invalid_package(
+            "the latest Anki package format is not supported, export it with \"Support older Anki versions\" enabled".to_owned(),
+        ));
+    }
+    let collection_name = ["collection.anki21", "collection.anki2"]
+        .into_iter()
+        .find(|name| archive.index_for_name(name).is_some())
+        .ok_or_else(|| invalid_package("there is no Anki collection".to_owned()))?;
+    let mut collection_file = NamedTempFile::new()?;
+    std::io::copy(&mut archive.by_name(collection_name)?, &mut collection_file)?;
+    let conn = Connection::open(collection_file.path())?;
+
+    let (crt, models_raw, decks_raw): (i64, String, String) =
+        conn.query_row("select crt, models, decks from col", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+    let collection_created = DateTime::from_timestamp(crt, 0)
+        .ok_or_else(|| invalid_package(format!("invalid collection creation time {}", crt)))?;
+    let deck_names = read_deck_names(&conn, &decks_raw)?;
+    let cloze_note_type_ids = read_cloze_note_type_ids(&models_raw)?;
+
+    let mut notes: HashMap<i64, AnkiNote> = HashMap::new();
+    let mut stmt = conn.prepare("select id, mid, flds from notes")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let fields: String = row.get(2)?;
+        notes.insert(
+            row.get(0)?,
+            AnkiNote {
+                fields: fields.split(FIELD_SEPARATOR).map(str::to_owned).collect(),
+                is_cloze: cloze_note_type_ids.contains(&row.get(1)?),
+            },
+        );
+    }
+
+    // The first card of every note, filtered decks are replaced with the original ones.
+    let mut anki_cards: Vec<AnkiCard> = Vec::new();
+    let mut stmt = conn.prepare(
+        "select id, nid, case when odid = 0 then did else odid end,
+         type, case when odid = 0 then due else odue end, ivl, reps, lapses, data
+         from cards order by nid, ord",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let note_id: i64 = row.get(1)?;
+        if anki_cards.last().is_some_and(|ac| ac.note_id == note_id) {
+            continue;
+        }
+        anki_cards.push(AnkiCard {
+            id: row.get(0)?,
+            note_id,
+            deck_id: row.get(2)?,
+            card_type: row.get(3)?,
+            due: row.get(4)?,
+            ivl: row.get(5)?,
+            reps: row.get(6)?,
+            lapses: row.get(7)?,
+            data: row.get(8)?,
+        });
+    }
+
+    let mut anki_cards_by_page: BTreeMap<PathBuf, Vec<AnkiCard>> = BTreeMap::new();
+    for anki_card in anki_cards {
+        let deck_name = deck_names.get(&anki_card.deck_id).map_or("Default", String::as_str);
+        let page_path = graph_root.join("pages").join(page_file_name(deck_name));
+        anki_cards_by_page.entry(page_path).or_default().push(anki_card);
+    }
+    // Check before writing anything, so that a failed import can simply be retried.
+    if let Some(page_path) = anki_cards_by_page.keys().find(|p| p.exists()) {
+        return Err(Error::AlreadyExists(page_path.clone()));
+    }
+
+    let asset_names = import_media(package, &mut archive, &graph_root.join("assets"))?;
+
+    let mut stmt = conn.prepare("select id, ease, time from revlog where cid = ?1 order by id")?;
+    let mut imported_cards = 0;
+    for (page_path, anki_cards) in &anki_cards_by_page {
+        let source_path = Arc::new(page_path.clone());
+        let mut cards = Vec::new();
+        let mut review_logs_by_card = Vec::new();
+        for anki_card in anki_cards {
+            let Some(note) = notes.get(&anki_card.note_id) else { continue };
+            let mut anki_reviews = Vec::new();
+            let mut rows = stmt.query([anki_card.id])?;
+            while let Some(row) = rows.next()? {
+                let Some(rating) = rating_from_ease(row.get(1)?) else { continue };
+                let reviewed_at = DateTime::from_timestamp_millis(row.get(0)?)
+                    .ok_or_else(|| invalid_package("invalid review log id".to_owned()))?;
+                let duration_ms = row.get::<_, i64>(2)?.max(0) as u64;
+                anki_reviews.push(AnkiReview { reviewed_at, rating, duration_ms });
+            }
+
+            let body = note_to_card_body(note, &asset_names);
+            let (fsrs_meta, review_logs) =
+                compute_fsrs_meta(anki_card, &anki_reviews, collection_created);
+            cards.push(Card {
+                metadata: CardMetadata {
+                    card_ref: CardRef {
+                        source_path: source_path.clone(),
+                        prompt_fingerprint: body.prompt.as_str().into(),
+                        serial_num: None,
+                    },
+                    srs_meta: SRSMeta {
+                        logseq_srs_meta: (&fsrs_meta).into(),
+                        fsrs_meta,
+                        suspended: false,
+                    },
+                    graph_index: None,
+                },
+                body,
+            });
+            let durations_ms = anki_reviews.iter().map(|ar| ar.duration_ms);
+            review_logs_by_card.push(review_logs.into_iter().zip(durations_ms).collect::<Vec<_>>());
+        }
+
+        storage_manager.add_cards_to_page(page_path, &mut cards)?;
+        imported_cards += cards.len();
+
+        let mut card_review_logs = Vec::new();
+        for (card, review_logs) in cards.iter().zip(review_logs_by_card) {
+            let Some(serial_num) = card.metadata.card_ref.serial_num else { continue };
+            card_review_logs.extend(review_logs.into_iter().map(|(review_log, duration_ms)| {
+                CardReviewLog { serial_num, review_log, review_duration_ms: Some(duration_ms) }
+            }));
+        }
+        storage_manager.append_review_logs(&card_review_logs)?;
+    }
+
+    Ok(AnkiImportSummary {
+        cards: imported_cards,
+        pages: anki_cards_by_page.len(),
+        media_files: asset_names.len(),
+    })
+}
@@ -0,0 +1,308 @@
+use std::env;
+use std::sync::LazyLock;
+
+use crossterm::style::Colored;
+use crossterm::style::ContentStyle;
+use crossterm::style::Stylize;
+use markdown::mdast::AlignKind;
+use markdown::mdast::Node;
+use markdown::mdast::Table;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::Theme;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use syntect::util::as_24_bit_terminal_escaped;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Result;
+use crate::html::parse_markdown;
+
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+static THEME: LazyLock<Theme> = LazyLock::new(|| {
+    ThemeSet::load_defaults()
+        .themes
+        .remove("base16-ocean.dark")
+        .expect("base16-ocean.dark is one of syntect's default themes")
+});
+
+// A piece of inline text in a single style, `\n` marks a hard line break.
+struct Span {
+    text: String,
+    style: ContentStyle,
+}
+
+fn styled(text: &str, style: ContentStyle) -> String {
+    style.apply(text).to_string()
+}
+
+fn collect_children(node: &Node, style: ContentStyle, spans: &mut Vec<Span>) {
+    for child in node.children().into_iter().flatten() {
+        collect_spans(child, style, spans);
+    }
+}
+
+// Soft line breaks are joined into spaces, lines are wrapped later.
+fn collect_spans(node: &Node, style: ContentStyle, spans: &mut Vec<Span>) {
+    let mut push = |text: &str, style: ContentStyle| {
+        spans.push(Span { text: text.replace('\n', " "), style });
+    };
+    match node {
+        Node::Text(text) => push(&text.value, style),
+        Node::InlineCode(code) => push(&code.value, style.yellow()),
+        Node::InlineMath(math) => push(&math.value, style.cyan()),
+        Node::Html(raw) => push(&raw.value, style.dim()),
+        Node::Image(image) => push(&format!("[image: {}]", image.url), style.dim()),
+        Node::ImageReference(image) => push(&image.alt, style),
+        Node::Break(_) => spans.push(Span { text: "\n".to_owned(), style }),
+        Node::Emphasis(_) => collect_children(node, style.italic(), spans),
+        Node::Strong(_) => collect_children(node, style.bold(), spans),
+        Node::Delete(_) => collect_children(node, style.crossed_out(), spans),
+        Node::Link(link) => {
+            let start = spans.len();
+            collect_children(node, style.underlined().blue(), spans);
+            // Autolinks already show the URL as their text.
+            let text: String = spans[start..].iter().map(|span| span.text.as_str()).collect();
+            if text != link.url {
+                spans.push(Span { text: format!(" ({})", link.url), style: style.dim() });
+            }
+        }
+        Node::Definition(_) | Node::FootnoteDefinition(_) => {}
+        _ => collect_children(node, style, spans),
+    }
+}
+
+fn inline_spans(node: &Node, style: ContentStyle) -> Vec<Span> {
+    let mut spans = Vec::new();
+    collect_children(node, style, &mut spans);
+    spans
+}
+
+// Words are kept whole, a word wider than the line gets a line of its own.
+fn wrap_spans(spans: &[Span], width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    let mut pending_space = false;
+    for span in spans {
+        for (i, segment) in span.text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+                pending_space = false;
+            }
+            for (j, word) in segment.split(' ').enumerate() {
+                pending_space |= j > 0;
+                if word.is_empty() {
+                    continue;
+                }
+                let word_width = word.width();
+                let mut space = usize::from(pending_space && line_width > 0);
+                if line_width > 0 && line_width + space + word_width > width {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                    space = 0;
+                }
+                line.push_str(&" ".repeat(space));
+                line.push_str(&styled(word, span.style));
+                line_width += space + word_width;
+                pending_space = false;
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn prefix_lines(lines: Vec<String>, first: &str, rest: &str) -> Vec<String> {
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { rest };
+            if line.is_empty() {
+                prefix.trim_end().to_owned()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect()
+}
+
+// The highlighting theme needs 24-bit colors, which terminals announce with COLORTERM.
+// NO_COLOR turns them off like the rest of the styles' colors.
+fn highlighting_enabled() -> bool {
+    !Colored::ansi_color_disabled_memoized()
+        && matches!(env::var("COLORTERM").as_deref(), Ok("truecolor" | "24bit"))
+}
+
+// Unknown languages are highlighted as plain text,
+// code is left plain where it can't be highlighted.
+fn highlight_code(code: &str, lang: Option<&str>) -> Vec<String> {
+    if !highlighting_enabled() {
+        return code.lines().map(str::to_owned).collect();
+    }
+    let syntax = lang
+        .and_then(|lang| SYNTAX_SET.find_syntax_by_token(lang))
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, &THEME);
+    LinesWithEndings::from(code)
+        .map(|line| match highlighter.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => {
+                let escaped = as_24_bit_terminal_escaped(&ranges, false);
+                format!("{}\x1b[0m", escaped.trim_end_matches('\n'))
+            }
+            Err(_) => line.trim_end_matches('\n').to_owned(),
+        })
+        .collect()
+}
+
+fn render_table(table: &Table) -> Vec<String> {
+    // (styled text, width) of every cell, the first row is the header.
+    let rows: Vec<Vec<(String, usize)>> = table
+        .children
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let style = if i == 0 { ContentStyle::new().bold() } else { ContentStyle::new() };
+            row.children()
+                .into_iter()
+                .flatten()
+                .map(|cell| {
+                    let spans = inline_spans(cell, style);
+                    let text = spans.iter().map(|span| styled(&span.text, span.style)).collect();
+                    let width = spans.iter().map(|span| span.text.width()).sum();
+                    (text, width)
+                })
+                .collect()
+        })
+        .collect();
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let column_widths: Vec<usize> = (0..columns)
+        .map(|j| rows.iter().filter_map(|row| row.get(j)).map(|(_, w)| *w).max().unwrap_or(0))
+        .collect();
+
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = column_widths
+            .iter()
+            .enumerate()
+            .map(|(j, column_width)| {
+                let (text, width) = row.get(j).map_or(("", 0), |(t, w)| (t.as_str(), *w));
+                let padding = column_width - width;
+                match table.align.get(j) {
+                    Some(AlignKind::Right) => format!("{}{}", " ".repeat(padding), text),
+                    Some(AlignKind::Center) => format!(
+                        "{}{}{}",
+                        " ".repeat(padding / 2),
+                        text,
+                        " ".repeat(padding - padding / 2)
+                    ),
+                    _ => format!("{}{}", text, " ".repeat(padding)),
+                }
+            })
+            .collect();
+        lines.push(cells.join(" │ ").trim_end().to_owned());
+        if i == 0 {
+            let rules: Vec<String> = column_widths.iter().map(|w| "─".repeat(*w)).collect();
+            lines.push(rules.join("─┼─"));
+        }
+    }
+    lines
+}
+
+fn render_blocks<'a>(
+    nodes: impl IntoIterator<Item = &'a Node>,
+    width: usize,
+    tight: bool,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for node in nodes {
+        let block = render_block(node, width);
+        if block.is_empty() {
+            continue;
+        }
+        if !lines.is_empty() && !tight {
+            lines.push(String::new());
+        }
+        lines.extend(block);
+    }
+    lines
+}
+
+fn render_block(node: &Node, width: usize) -> Vec<String> {
+    match node {
+        Node::Root(root) => render_blocks(&root.children, width, false),
+        Node::Heading(heading) => {
+            let style = ContentStyle::new().bold();
+            let mut spans =
+                vec![Span { text: format!("{} ", "#".repeat(heading.depth.into())), style }];
+            spans.extend(inline_spans(node, style));
+            wrap_spans(&spans, width)
+        }
+        Node::Blockquote(blockquote) => {
+            let bar = styled("│ ", ContentStyle::new().dim());
+            let lines = render_blocks(&blockquote.children, width.saturating_sub(2), false);
+            prefix_lines(lines, &bar, &bar)
+        }
+        Node::List(list) => {
+            let tight = !list.spread
+                && list.children.iter().all(|item| match item {
+                    Node::ListItem(item) => !item.spread,
+                    _ => true,
+                });
+            let mut lines = Vec::new();
+            for (i, item) in list.children.iter().enumerate() {
+                if i > 0 && !tight {
+                    lines.push(String::new());
+                }
+                let mut bullet = match list.ordered {
+                    true => format!("{}. ", list.start.unwrap_or(1) as usize + i),
+                    false => "• ".to_owned(),
+                };
+                if let Node::ListItem(list_item) = item {
+                    match list_item.checked {
+                        Some(true) => bullet.push_str("[x] "),
+                        Some(false) => bullet.push_str("[ ] "),
+                        None => {}
+                    }
+                }
+                let indent = bullet.width();
+                let mut item_lines = render_blocks(
+                    item.children().into_iter().flatten(),
+                    width.saturating_sub(indent),
+                    tight,
+                );
+                if item_lines.is_empty() {
+                    item_lines.push(String::new());
+                }
+                lines.extend(prefix_lines(item_lines, &bullet, &" ".repeat(indent)));
+            }
+            lines
+        }
+        Node::Code(code) => highlight_code(&code.value, code.lang.as_deref()),
+        Node::Math(math) => {
+            math.value.lines().map(|line| styled(line, ContentStyle::new().cyan())).collect()
+        }
+        Node::ThematicBreak(_) => vec![styled(&"─".repeat(width), ContentStyle::new().dim())],
+        Node::Table(table) => render_table(table),
+        Node::Html(raw) => {
+            raw.value.lines().map(|line| styled(line, ContentStyle::new().dim())).collect()
+        }
+        Node::Definition(_) | Node::FootnoteDefinition(_) => Vec::new(),
+        _ => wrap_spans(&inline_spans(node, ContentStyle::new()), width),
+    }
+}
+
+// Renders markdown with terminal styles, wrapping text to `width` columns.
+// Code blocks and tables are left unwrapped, as breaking their lines would garble them.
+pub fn markdown_to_ansi(markdown: &str, width: usize) -> Result<String> {
+    let tree = parse_markdown(markdown)?;
+    let mut ansi = render_block(&tree, width).join("\n");
+    ansi.push('\n');
+    Ok(ansi)
+}
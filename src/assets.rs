@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use markdown::mdast::Node;
+use percent_encoding::percent_decode_str;
+
+use crate::error::Result;
+use crate::html::has_url_scheme;
+use crate::html::parse_markdown;
+use crate::storage::normalize_path;
+
+// Where a reference in a card leads.
+pub enum AssetLookup {
+    // URLs with a scheme, absolute paths and fragments do not point at assets.
+    NotAsset,
+    Found(PathBuf),
+    // Nothing is there, holds where the asset was looked for relative to the page.
+    Missing(PathBuf),
+}
+
+// Logseq references assets relative to the page (`../assets/x.png`)
+// or relative to the graph root (`assets/x.png`), see [ref:logseq-dir-layout].
+// The URL is percent-decoded first, as in `../assets/my%20image.png`.
+pub fn find_asset(url: &str, page_dir: &Path, graph_root: &Path) -> AssetLookup {
+    if url.is_empty() || has_url_scheme(url) || url.starts_with(['/', '#']) {
+        return AssetLookup::NotAsset;
+    }
+    let decoded = percent_decode_str(url).decode_utf8_lossy();
+    let asset = normalize_path(&page_dir.join(decoded.as_ref()));
+    match [asset.clone(), graph_root.join(decoded.as_ref())].into_iter().find(|p| p.is_file()) {
+        Some(path) => AssetLookup::Found(path),
+        None => AssetLookup::Missing(asset),
+    }
+}
+
+// Backslash escapes any ASCII punctuation in markdown, so the text is read back as it is.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The destination is written in angle brackets, which allow spaces in paths.
+fn destination_markdown(url: &str, title: Option<&str>) -> String {
+    let mut markdown = format!("(<{}>", escape_markdown(url));
+    if let Some(title) = title {
+        markdown.push_str(&format!(" \"{}\"", escape_markdown(title)));
+    }
+    markdown.push(')');
+    markdown
+}
+
+fn collect_references<'a>(node: &'a Node, links: bool, references: &mut Vec<&'a Node>) {
+    match node {
+        Node::Image(_) => references.push(node),
+        Node::Link(_) if links => references.push(node),
+        _ => {}
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_references(child, links, references);
+    }
+}
+
+// Points images, and links too when `links` is set, at the URL `rewrite` returns for them,
+// in the order they appear in. Those it returns None for are left as they are.
+// References are replaced where the parser found them, which handles any way of writing them,
+// like `<my image.png>` or a title after the URL.
+pub fn rewrite_urls(
+    markdown: &str,
+    links: bool,
+    mut rewrite: impl FnMut(&str) -> Result<Option<String>>,
+) -> Result<String> {
+    let tree = parse_markdown(markdown)?;
+    let mut references = Vec::new();
+    collect_references(&tree, links, &mut references);
+
+    let mut replacements = Vec::new();
+    for reference in references {
+        let (url, title) = match reference {
+            Node::Image(image) => (&image.url, &image.title),
+            Node::Link(link) => (&link.url, &link.title),
+            _ => continue,
+        };
+        let Some(new_url) = rewrite(url)? else { continue };
+        let Some(position) = reference.position() else { continue };
+        let destination = destination_markdown(&new_url, title.as_deref());
+        let replacement = match reference {
+            Node::Image(image) => format!("![{}]{}", escape_markdown(&image.alt), destination),
+            // The link text may hold markdown of its own, so it is kept as it is written.
+            _ => {
+                let children = reference.children().map(Vec::as_slice).unwrap_or_default();
+                let text = match (children.first(), children.last()) {
+                    (Some(first), Some(last)) => match (first.position(), last.position()) {
+                        (Some(start), Some(end)) => &markdown[start.start.offset..end.end.offset],
+                        _ => "",
+                    },
+                    _ => "",
+                };
+                format!("[{}]{}", text, destination)
+            }
+        };
+        replacements.push((position.start.offset..position.end.offset, replacement));
+    }
+
+    // From the last one, so that the positions of the others stay valid.
+    let mut rewritten = markdown.to_owned();
+    for (range, replacement) in replacements.into_iter().rev() {
+        rewritten.replace_range(range, &replacement);
+    }
+    Ok(rewritten)
+}
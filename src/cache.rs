@@ -0,0 +1,141 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use etcetera::AppStrategy;
+use etcetera::AppStrategyArgs;
+use etcetera::choose_app_strategy;
+use serde::Serialize;
+use tempfile::NamedTempFile;
+
+use crate::error::PathContext;
+use crate::error::Result;
+use crate::settings::OutputSettings;
+
+// Same strategy as for the configuration file, so `~/.cache/losrs` on Linux.
+pub fn cache_dir() -> Result<PathBuf> {
+    let strategy = choose_app_strategy(AppStrategyArgs {
+        top_level_domain: "rs".to_owned(),
+        author: "".to_owned(),
+        app_name: "losrs".to_owned(),
+    })?;
+    Ok(strategy.cache_dir())
+}
+
+fn images_dir() -> Result<PathBuf> {
+    Ok(cache_dir()?.join("images"))
+}
+
+// Edited assets keep their path, their modification time and size tell them apart.
+#[derive(Serialize)]
+struct AssetStamp<'a> {
+    path: &'a Path,
+    modified: Option<SystemTime>,
+    size: Option<u64>,
+}
+
+impl<'a> AssetStamp<'a> {
+    fn new(path: &'a Path) -> Self {
+        let metadata = fs::metadata(path).ok();
+        AssetStamp {
+            path,
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+            size: metadata.map(|m| m.len()),
+        }
+    }
+}
+
+// Everything the rendered image depends on, the version covers changes to rendering itself.
+#[derive(Serialize)]
+struct ImageKey<'a> {
+    version: &'a str,
+    markdown: &'a str,
+    card_body_parts: u8,
+    output_settings: &'a OutputSettings,
+    term_size: (u16, u16),
+    graph_root: &'a Path,
+    assets: Vec<AssetStamp<'a>>,
+}
+
+pub(crate) fn image_key(
+    markdown: &str,
+    card_body_parts: u8,
+    output_settings: &OutputSettings,
+    term_size: (u16, u16),
+    graph_root: &Path,
+    assets: &[PathBuf],
+) -> Result<String> {
+    let key = ImageKey {
+        version: env!("CARGO_PKG_VERSION"),
+        markdown,
+        card_body_parts,
+        output_settings,
+        term_size,
+        graph_root,
+        assets: assets.iter().map(|path| AssetStamp::new(path)).collect(),
+    };
+    let hash = xxhash_rust::xxh3::xxh3_128(serde_json::to_string(&key)?.as_bytes());
+    Ok(format!("{:032x}", hash))
+}
+
+// The first page is stored as `<key>.png`, the following ones as `<key>-<page>.png`.
+fn page_path(dir: &Path, key: &str, page: usize) -> PathBuf {
+    match page {
+        0 => dir.join(format!("{}.png", key)),
+        _ => dir.join(format!("{}-{}.png", key, page + 1)),
+    }
+}
+
+fn read_page(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(png_buf) => Ok(Some(png_buf)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_path(path),
+    }
+}
+
+pub(crate) fn load_images(key: &str) -> Result<Option<Vec<Vec<u8>>>> {
+    let dir = images_dir()?;
+    let Some(first) = read_page(&page_path(&dir, key, 0))? else {
+        return Ok(None);
+    };
+    let mut pages = vec![first];
+    while let Some(png_buf) = read_page(&page_path(&dir, key, pages.len()))? {
+        pages.push(png_buf);
+    }
+    Ok(Some(pages))
+}
+
+// Each page is written to a temporary file first, so a concurrent reader never sees half an image.
+// The first page is written last, as finding it means the rest are in place.
+pub(crate) fn store_images(key: &str, pages: &[Vec<u8>]) -> Result<()> {
+    let dir = images_dir()?;
+    fs::create_dir_all(&dir).with_path(&dir)?;
+    for (page, png_buf) in pages.iter().enumerate().rev() {
+        let mut file = NamedTempFile::new_in(&dir).with_path(&dir)?;
+        file.write_all(png_buf)?;
+        let path = page_path(&dir, key, page);
+        file.persist(&path).map_err(|e| e.error).with_path(&path)?;
+    }
+    Ok(())
+}
+
+// Removes all cached images, returning how many there were.
+pub fn clear() -> Result<usize> {
+    let dir = images_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).with_path(&dir),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry.with_path(&dir)?.path();
+        fs::remove_file(&path).with_path(&path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::io::Write;
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Days;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use crossterm::QueueableCommand;
+use crossterm::style::Color;
+use crossterm::style::Colored;
+use crossterm::style::Print;
+use crossterm::style::PrintStyledContent;
+use crossterm::style::Stylize;
+
+use crate::error::Result;
+use crate::types::CardMetadata;
+use crate::types::CardReviewLog;
+
+const HISTORY_WEEKS: u64 = 53;
+const FORECAST_WEEKS: u64 = 13;
+
+// Glyphs differ per level so that the heatmap is readable without colours (NO_COLOR).
+const LEVEL_GLYPHS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+const LEVEL_COLORS: [Color; 5] = [
+    Color::DarkGrey,
+    Color::Rgb { r: 0x0e, g: 0x44, b: 0x29 },
+    Color::Rgb { r: 0x00, g: 0x6d, b: 0x32 },
+    Color::Rgb { r: 0x26, g: 0xa6, b: 0x41 },
+    Color::Rgb { r: 0x39, g: 0xd3, b: 0x53 },
+];
+const WEEKDAY_LABELS: [&str; 7] = ["Mon", "   ", "Wed", "   ", "Fri", "   ", "   "];
+
+type CountsByDate = BTreeMap<NaiveDate, u32>;
+
+fn level(count: u32, max_count: u32) -> usize {
+    if count == 0 || max_count == 0 {
+        return 0;
+    }
+    // Levels 1..=4 split (0, max_count] into equal parts.
+    (count * 4).div_ceil(max_count).clamp(1, 4) as usize
+}
+
+fn print_level(mut writer: impl Write, level: usize) -> Result<()> {
+    // Styled content still emits (empty) escape sequences when colours are disabled.
+    if Colored::ansi_color_disabled_memoized() {
+        writer.queue(Print(LEVEL_GLYPHS[level]))?;
+    } else {
+        writer.queue(PrintStyledContent(LEVEL_GLYPHS[level].with(LEVEL_COLORS[level])))?;
+    }
+    Ok(())
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+// Draws weeks as columns and weekdays as rows, starting with the week of `first_date`.
+fn draw_heatmap(
+    mut writer: impl Write,
+    counts_by_date: &CountsByDate,
+    first_date: NaiveDate,
+    last_date: NaiveDate,
+) -> Result<()> {
+    let first_week_start = week_start(first_date);
+    let weeks = (last_date - first_week_start).num_days() as u64 / 7 + 1;
+    let max_count = counts_by_date.range(first_date..=last_date).map(|(_, c)| *c).max();
+    let max_count = max_count.unwrap_or_default();
+
+    // Month labels are placed above the first week that starts in that month,
+    // unless the previous label is still being drawn.
+    let mut month_labels = String::new();
+    for week in 0..weeks {
+        if month_labels.chars().count() > week as usize {
+            continue;
+        }
+        let week_start = first_week_start + Days::new(week * 7);
+        if week == 0 || week_start.day() <= 7 {
+            month_labels.push_str(&week_start.format("%b").to_string());
+        } else {
+            month_labels.push(' ');
+        }
+    }
+    writer.queue(Print(format!("    {}\n", month_labels.trim_end())))?;
+
+    for (weekday, weekday_label) in WEEKDAY_LABELS.iter().enumerate() {
+        writer.queue(Print(format!("{} ", weekday_label)))?;
+        for week in 0..weeks {
+            let date = first_week_start + Days::new(week * 7 + weekday as u64);
+            if date > last_date {
+                break;
+            }
+            if date < first_date {
+                writer.queue(Print(' '))?;
+                continue;
+            }
+            let level = level(counts_by_date.get(&date).copied().unwrap_or_default(), max_count);
+            print_level(&mut writer, level)?;
+        }
+        writer.queue(Print('\n'))?;
+    }
+
+    writer.queue(Print("    Less "))?;
+    for level in 0..LEVEL_GLYPHS.len() {
+        print_level(&mut writer, level)?;
+    }
+    writer.queue(Print(format!(" More (max {} per day)\n", max_count)))?;
+    Ok(())
+}
+
+struct Streaks {
+    current: u32,
+    longest: u32,
+    missed_days: u32,
+}
+
+fn compute_streaks(
+    counts_by_date: &CountsByDate,
+    first_date: NaiveDate,
+    today: NaiveDate,
+) -> Streaks {
+    let reviewed = |date: &NaiveDate| counts_by_date.get(date).is_some_and(|c| *c > 0);
+
+    let mut streaks = Streaks { current: 0, longest: 0, missed_days: 0 };
+    // Days before the first review are not counted as missed.
+    let Some(first_review_date) = counts_by_date.range(first_date..=today).map(|(d, _)| *d).next()
+    else {
+        return streaks;
+    };
+
+    let mut streak = 0;
+    for date in first_review_date.iter_days().take_while(|d| *d < today) {
+        if reviewed(&date) {
+            streak += 1;
+            streaks.longest = streaks.longest.max(streak);
+        } else {
+            streak = 0;
+            streaks.missed_days += 1;
+        }
+    }
+    // Not having reviewed yet today does not break the streak.
+    if reviewed(&today) {
+        streak += 1;
+        streaks.longest = streaks.longest.max(streak);
+    }
+    streaks.current = streak;
+    streaks
+}
+
+pub fn show_calendar(
+    card_metas: &[CardMetadata],
+    card_review_logs: Option<&[CardReviewLog]>,
+    at: DateTime<FixedOffset>,
+) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    let today = at.date_naive();
+
+    match card_review_logs {
+        Some(card_review_logs) => {
+            // Review logs cover the whole graph, only count those of the selected cards.
+            let csns: HashSet<u64> =
+                card_metas.iter().filter_map(|cm| cm.card_ref.serial_num).collect();
+            let mut reviews_by_date = CountsByDate::new();
+            for crl in card_review_logs.iter().filter(|crl| csns.contains(&crl.serial_num)) {
+                let date = crl.review_log.reviewed_date.with_timezone(at.offset()).date_naive();
+                *reviews_by_date.entry(date).or_default() += 1;
+            }
+            let first_date = week_start(today) - Days::new((HISTORY_WEEKS - 1) * 7);
+            let total: u32 = reviews_by_date.range(first_date..=today).map(|(_, c)| *c).sum();
+
+            stdout.queue(Print(format!("Reviews in the last year: {}\n", total)))?;
+            draw_heatmap(&mut stdout, &reviews_by_date, first_date, today)?;
+
+            let streaks = compute_streaks(&reviews_by_date, first_date, today);
+            stdout.queue(Print(format!(
+                "Streak in days: {} current, {} longest, {} missed\n",
+                streaks.current, streaks.longest, streaks.missed_days
+            )))?;
+        }
+        None => {
+            stdout.queue(Print(
+                "No review history, use in-graph-root storage.metadata_mode\n".to_owned(),
+            ))?;
+        }
+    }
+
+    // Overdue cards are due today.
+    let mut due_by_date = CountsByDate::new();
+    for fsrs_meta in card_metas.iter().map(|cm| &cm.srs_meta.fsrs_meta) {
+        let date = fsrs_meta.due.with_timezone(at.offset()).date_naive().max(today);
+        *due_by_date.entry(date).or_default() += 1;
+    }
+    let last_date = week_start(today) + Days::new(FORECAST_WEEKS * 7 - 1);
+    let total: u32 = due_by_date.range(today..=last_date).map(|(_, c)| *c).sum();
+
+    stdout.queue(Print(format!("\nDue in the next {} weeks: {}\n", FORECAST_WEEKS, total)))?;
+    draw_heatmap(&mut stdout, &due_by_date, today, last_date)?;
+    stdout.queue(Print(format!(
+        "Due today: {}, tomorrow: {}\n",
+        due_by_date.get(&today).copied().unwrap_or_default(),
+        due_by_date.get(&(today + Days::new(1))).copied().unwrap_or_default(),
+    )))?;
+
+    stdout.flush()?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use chrono::Utc;
+use rs_fsrs::State;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::error::PathContext;
+use crate::error::Result;
+use crate::storage::StorageManager;
+use crate::types::Card;
+use crate::types::CardBody;
+use crate::types::CardMetadata;
+use crate::types::CardRef;
+use crate::types::FSRSMeta;
+use crate::types::Fingerprint;
+use crate::types::SRSMeta;
+
+#[derive(Serialize)]
+struct CardRow {
+    csn: Option<u64>,
+    page: String,
+    fingerprint: Fingerprint,
+    prompt: String,
+    response: String,
+    due: DateTime<Utc>,
+    stability: f64,
+    difficulty: f64,
+    state: State,
+    reps: i32,
+    lapses: i32,
+}
+
+fn delimiter(tsv: bool) -> u8 {
+    if tsv { b'\t' } else { b',' }
+}
+
+// Returns the number of exported cards.
+pub fn export_csv(
+    storage_manager: &StorageManager,
+    path: &Path,
+    writer: impl Write,
+    tsv: bool,
+) -> Result<usize> {
+    let mut card_metas = storage_manager.select_card_metadata(path, None)?;
+    card_metas.sort_by(|a, b| a.card_ref.source_path.cmp(&b.card_ref.source_path));
+
+    let mut csv_writer = csv::WriterBuilder::new().delimiter(delimiter(tsv)).from_writer(writer);
+    for cm in &card_metas {
+        let body = storage_manager.load_card_body_by_ref(&cm.card_ref)?;
+        let fsrs_meta = &cm.srs_meta.fsrs_meta;
+        csv_writer.serialize(CardRow {
+            csn: cm.card_ref.serial_num,
+            page: cm.card_ref.source_path.file_stem().unwrap_or_default().to_string_lossy().into(),
+            fingerprint: cm.card_ref.prompt_fingerprint.clone(),
+            prompt: body.prompt_text(),
+            response: body.response_text(),
+            due: fsrs_meta.due,
+            stability: fsrs_meta.stability,
+            difficulty: fsrs_meta.difficulty,
+            state: fsrs_meta.state,
+            reps: fsrs_meta.reps,
+            lapses: fsrs_meta.lapses,
+        })?;
+    }
+    csv_writer.flush()?;
+    Ok(card_metas.len())
+}
+
+// Reads question and answer pairs, one per row, as new cards for the page.
+// Nothing is written, so that a bad file leaves the graph untouched.
+pub fn read_csv(file: &Path, page_path: &Path, tsv: bool, has_header: bool) -> Result<Vec<Card>> {
+    let invalid_csv = |message: String| Error::InvalidFile { path: file.to_path_buf(), message };
+    let csv_file = File::open(file).with_path(file)?;
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter(tsv))
+        .has_headers(has_header)
+        .flexible(true)
+        .from_reader(csv_file);
+
+    let source_path = Arc::new(page_path.to_path_buf());
+    let mut cards = Vec::new();
+    for record in csv_reader.records() {
+        let record = record.map_err(|e| invalid_csv(e.to_string()))?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let [prompt, response] = [0, 1].map(|i| record.get(i));
+        let (Some(prompt), Some(response), 2) = (prompt, response, record.len()) else {
+            return Err(invalid_csv(format!(
+                "expected 2 columns (question and answer) on line {}, got {}",
+                line,
+                record.len()
+            )));
+        };
+        if prompt.trim().is_empty() {
+            return Err(invalid_csv(format!("empty question on line {}", line)));
+        }
+
+        let body = CardBody::from_text(prompt, response);
+        let fsrs_meta = FSRSMeta::default();
+        cards.push(Card {
+            metadata: CardMetadata {
+                card_ref: CardRef {
+                    source_path: source_path.clone(),
+                    prompt_fingerprint: body.prompt.as_str().into(),
+                    serial_num: None,
+                },
+                srs_meta: SRSMeta {
+                    logseq_srs_meta: (&fsrs_meta).into(),
+                    fsrs_meta,
+                    suspended: false,
+                },
+                graph_index: None,
+            },
+            body,
+        });
+    }
+
+    Ok(cards)
+}
+
+// Adds the cards read by `read_csv` to the page, returns the number of imported cards.
+pub fn import_csv(
+    storage_manager: &mut StorageManager,
+    page_path: &Path,
+    mut cards: Vec<Card>,
+) -> Result<usize> {
+    storage_manager.add_cards_to_page(page_path, &mut cards)?;
+    Ok(cards.len())
+}
@@ -0,0 +1,104 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::Utc;
+
+use crate::settings::OutputFormat;
+use crate::types::Fingerprint;
+
+// The step of rendering a card that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenderTool {
+    Markdown,
+    TypstTranslator,
+    Typst,
+    Pandoc,
+    Image,
+}
+
+impl Display for RenderTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RenderTool::Markdown => "markdown",
+            RenderTool::TypstTranslator => "typst translator",
+            RenderTool::Typst => "typst",
+            RenderTool::Pandoc => "pandoc",
+            RenderTool::Image => "image",
+        })
+    }
+}
+
+// Errors of the library API, the binary wraps them into anyhow with extra context.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{} does not exist", .0.display())]
+    PathNotFound(PathBuf),
+    #[error("{} is a directory without a pages subdirectory, expected logseq graph root", .0.display())]
+    NotAGraphRoot(PathBuf),
+    #[error("there is no graph root for {}", .0.display())]
+    NoGraphRoot(PathBuf),
+    #[error(
+        "could not parse {}{}: {message}",
+        path.display(),
+        line.map(|l| format!(" at line {}", l)).unwrap_or_default()
+    )]
+    InvalidPage { path: PathBuf, line: Option<usize>, message: String },
+    // One of the files kept in graph root, like `.card-metadata.jsonl`,
+    // or a file being imported is corrupted.
+    #[error("could not parse {}: {message}", path.display())]
+    InvalidFile { path: PathBuf, message: String },
+    #[error("{} already exists", .0.display())]
+    AlreadyExists(PathBuf),
+    #[error("card with fingerprint {fingerprint} was not found in {}", path.display())]
+    CardNotFound { path: PathBuf, fingerprint: Fingerprint },
+    #[error("card with fingerprint {fingerprint} from {} does not have a serial number", path.display())]
+    MissingSerialNum { path: PathBuf, fingerprint: Fingerprint },
+    #[error(
+        "reviewing a card that was last reviewed at {last_review} at {reviewed_at}, before it was last reviewed!"
+    )]
+    ReviewBeforeLastReview { reviewed_at: DateTime<FixedOffset>, last_review: DateTime<Utc> },
+    #[error("failed to render card with {tool}: {message}")]
+    Render { tool: RenderTool, message: String },
+    #[error("card \"{prompt}\" in {} shows {url}, but {} does not exist", page.display(), asset.display())]
+    MissingAsset { page: PathBuf, prompt: String, url: String, asset: PathBuf },
+    // The user asked to leave the review right away.
+    #[error("Immediate nope out requested")]
+    NopeOut,
+    #[error("{0:?} is not an image output format")]
+    UnsupportedFormat(OutputFormat),
+    #[error("invalid configuration: {0}")]
+    Config(#[from] config::ConfigError),
+    #[error("could not access the configuration file: {0}")]
+    ConfigFile(#[from] confy::ConfyError),
+    #[error("could not find the cache directory: {0}")]
+    CacheDir(#[from] etcetera::HomeDirError),
+    #[error("when accessing {}: {source}", path.display())]
+    File { path: PathBuf, source: std::io::Error },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("could not access the Anki collection: {0}")]
+    AnkiCollection(#[from] rusqlite::Error),
+    #[error("could not access the Anki package: {0}")]
+    AnkiPackage(#[from] zip::result::ZipError),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+pub(crate) trait PathContext<T> {
+    fn with_path(self, path: &Path) -> Result<T>;
+}
+
+impl<T> PathContext<T> for std::io::Result<T> {
+    fn with_path(self, path: &Path) -> Result<T> {
+        self.map_err(|source| Error::File { path: path.to_path_buf(), source })
+    }
+}
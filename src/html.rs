@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use latex2mathml::DisplayStyle;
+use latex2mathml::latex_to_mathml;
+use markdown::ParseOptions;
+use markdown::mdast::AlignKind;
+use markdown::mdast::Node;
+use markdown::to_mdast;
+
+use crate::error::Error;
+use crate::error::RenderTool;
+use crate::error::Result;
+use crate::storage::normalize_path;
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 48em; margin: 0 auto; padding: 1em; line-height: 1.5; }
+header { color: gray; font-size: small; }
+img { max-width: 100%; }
+summary { cursor: pointer; margin: 1em 0; }
+button, summary { font-size: large; padding: 0.5em 1em; }
+pre { background: #f4f4f4; padding: 0.5em; overflow-x: auto; }
+blockquote { border-left: 0.25em solid gray; margin-left: 0; padding-left: 1em; }
+.response { border-top: 1px solid lightgray; }
+.card + .card { border-top: 1px solid lightgray; }
+";
+
+// Math is written as `$x^2$` inline and as `$$` fenced blocks, same as in Logseq.
+fn parse_options() -> ParseOptions {
+    let mut parse_options = ParseOptions::gfm();
+    parse_options.constructs.math_text = true;
+    parse_options.constructs.math_flow = true;
+    parse_options
+}
+
+// Relative asset paths are resolved against the page's directory
+// and made relative to the graph root, so `../assets/x.png` becomes `assets/x.png`.
+struct AssetBase<'a> {
+    page_dir: &'a Path,
+    graph_root: &'a Path,
+}
+
+struct HtmlWriter<'a> {
+    html: String,
+    asset_base: Option<AssetBase<'a>>,
+}
+
+impl HtmlWriter<'_> {
+    // URLs with a scheme, absolute paths and fragments are left as they are,
+    // as are paths that lead outside of the graph root.
+    fn asset_url(&self, url: &str) -> String {
+        let Some(AssetBase { page_dir, graph_root }) = &self.asset_base else {
+            return url.to_owned();
+        };
+        if url.is_empty() || has_url_scheme(url) || url.starts_with(['/', '#']) {
+            return url.to_owned();
+        }
+        match normalize_path(&page_dir.join(url)).strip_prefix(graph_root) {
+            Ok(relative) => relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => url.to_owned(),
+        }
+    }
+
+    // URLs that could run scripts are dropped, see `is_safe_url`.
+    fn safe_url(&self, url: &str, image: bool) -> Option<String> {
+        is_safe_url(url, image).then(|| self.asset_url(url))
+    }
+
+    // LaTeX that can not be converted is shown as is, rather than failing the whole card.
+    fn write_math(&mut self, latex: &str, display: DisplayStyle) {
+        match latex_to_mathml(latex, display) {
+            Ok(mathml) => self.html.push_str(&mathml),
+            Err(_) => {
+                self.html.push_str(&format!("<code class=\"math\">{}</code>", escape_html(latex)))
+            }
+        }
+    }
+
+    fn write_children(&mut self, node: &Node, tight: bool) {
+        for child in node.children().into_iter().flatten() {
+            self.write_node(child, tight);
+        }
+    }
+
+    fn write_wrapped(&mut self, tag: &str, node: &Node, tight: bool) {
+        self.html.push_str(&format!("<{}>", tag));
+        self.write_children(node, tight);
+        self.html.push_str(&format!("</{}>", tag));
+    }
+
+    // Paragraphs directly in items of tight lists are not wrapped in <p>,
+    // same as in CommonMark's reference renderer.
+    fn write_node(&mut self, node: &Node, tight: bool) {
+        match node {
+            Node::Root(_) => self.write_children(node, false),
+            Node::Paragraph(_) if tight => self.write_children(node, false),
+            Node::Paragraph(_) => self.write_wrapped("p", node, false),
+            Node::Heading(heading) => {
+                self.write_wrapped(&format!("h{}", heading.depth), node, false)
+            }
+            Node::Blockquote(_) => self.write_wrapped("blockquote", node, false),
+            Node::List(list) => {
+                let tight = !list.spread
+                    && list.children.iter().all(|item| match item {
+                        Node::ListItem(item) => !item.spread,
+                        _ => true,
+                    });
+                match (list.ordered, list.start) {
+                    (true, Some(start)) if start != 1 => {
+                        self.html.push_str(&format!("<ol start=\"{}\">", start))
+                    }
+                    (true, _) => self.html.push_str("<ol>"),
+                    (false, _) => self.html.push_str("<ul>"),
+                }
+                self.write_children(node, tight);
+                self.html.push_str(if list.ordered { "</ol>" } else { "</ul>" });
+            }
+            Node::ListItem(item) => {
+                self.html.push_str("<li>");
+                if let Some(checked) = item.checked {
+                    let checked = if checked { " checked" } else { "" };
+                    self.html.push_str(&format!("<input type=\"checkbox\" disabled{}> ", checked));
+                }
+                self.write_children(node, tight);
+                self.html.push_str("</li>");
+            }
+            Node::Text(text) => self.html.push_str(&escape_html(&text.value)),
+            Node::Emphasis(_) => self.write_wrapped("em", node, false),
+            Node::Strong(_) => self.write_wrapped("strong", node, false),
+            Node::Delete(_) => self.write_wrapped("del", node, false),
+            Node::Break(_) => self.html.push_str("<br>"),
+            Node::ThematicBreak(_) => self.html.push_str("<hr>"),
+            Node::InlineCode(code) => {
+                self.html.push_str(&format!("<code>{}</code>", escape_html(&code.value)))
+            }
+            Node::Code(code) => {
+                match &code.lang {
+                    Some(lang) => self
+                        .html
+                        .push_str(&format!("<pre><code class=\"language-{}\">", escape_html(lang))),
+                    None => self.html.push_str("<pre><code>"),
+                }
+                self.html.push_str(&escape_html(&code.value));
+                self.html.push_str("\n</code></pre>");
+            }
+            Node::InlineMath(math) => self.write_math(&math.value, DisplayStyle::Inline),
+            Node::Math(math) => self.write_math(&math.value, DisplayStyle::Block),
+            Node::Link(link) => {
+                self.html.push_str("<a");
+                if let Some(url) = self.safe_url(&link.url, false) {
+                    self.html.push_str(&format!(" href=\"{}\"", escape_html(&url)));
+                }
+                if let Some(title) = &link.title {
+                    self.html.push_str(&format!(" title=\"{}\"", escape_html(title)));
+                }
+                self.html.push('>');
+                self.write_children(node, false);
+                self.html.push_str("</a>");
+            }
+            Node::Image(image) => {
+                self.html.push_str("<img");
+                if let Some(url) = self.safe_url(&image.url, true) {
+                    self.html.push_str(&format!(" src=\"{}\"", escape_html(&url)));
+                }
+                self.html.push_str(&format!(" alt=\"{}\"", escape_html(&image.alt)));
+                if let Some(title) = &image.title {
+                    self.html.push_str(&format!(" title=\"{}\"", escape_html(title)));
+                }
+                self.html.push('>');
+            }
+            // Logseq cards carry HTML comments, like the serial number, which are passed through.
+            // Any other HTML is shown as text, cards and imported notes must not run scripts.
+            Node::Html(raw) if is_html_comment(&raw.value) => self.html.push_str(&raw.value),
+            Node::Html(raw) => self.html.push_str(&escape_html(&raw.value)),
+            Node::Table(table) => {
+                self.html.push_str("<table>");
+                for (i, row) in table.children.iter().enumerate() {
+                    let cell_tag = if i == 0 { "th" } else { "td" };
+                    self.html.push_str("<tr>");
+                    for (j, cell) in row.children().into_iter().flatten().enumerate() {
+                        let align = match table.align.get(j) {
+                            Some(AlignKind::Left) => " style=\"text-align: left\"",
+                            Some(AlignKind::Right) => " style=\"text-align: right\"",
+                            Some(AlignKind::Center) => " style=\"text-align: center\"",
+                            _ => "",
+                        };
+                        self.html.push_str(&format!("<{}{}>", cell_tag, align));
+                        self.write_children(cell, false);
+                        self.html.push_str(&format!("</{}>", cell_tag));
+                    }
+                    self.html.push_str("</tr>");
+                }
+                self.html.push_str("</table>");
+            }
+            // Definitions are only used to resolve references, they are not shown themselves.
+            Node::Definition(_) | Node::FootnoteDefinition(_) => {}
+            Node::ImageReference(image) => self.html.push_str(&escape_html(&image.alt)),
+            _ => self.write_children(node, false),
+        }
+    }
+}
+
+fn is_html_comment(raw: &str) -> bool {
+    raw.strip_prefix("<!--")
+        .and_then(|rest| rest.strip_suffix("-->"))
+        .is_some_and(|comment| !comment.contains("-->"))
+}
+
+// URLs like `https://..`, `data:..` or `mailto:..` do not point at files.
+pub(crate) fn has_url_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+    })
+}
+
+// Cards and imported notes must not run scripts, so only web and mail links,
+// paths, fragments and, for images, embedded images are kept.
+fn is_safe_url(url: &str, image: bool) -> bool {
+    // Browsers ignore whitespace and control characters in the scheme.
+    let url: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_control()).collect();
+    if !has_url_scheme(&url) {
+        return true;
+    }
+    let url = url.to_ascii_lowercase();
+    ["http:", "https:", "mailto:"].iter().any(|scheme| url.starts_with(scheme))
+        || (image && url.starts_with("data:image/"))
+}
+
+pub(crate) fn parse_markdown(markdown: &str) -> Result<Node> {
+    to_mdast(markdown, &parse_options()).map_err(|e| Error::Render {
+        tool: RenderTool::Markdown,
+        message: format!("could not parse markdown: {:?}", e),
+    })
+}
+
+fn render(markdown: &str, asset_base: Option<AssetBase>) -> Result<String> {
+    let tree = parse_markdown(markdown)?;
+    let mut writer = HtmlWriter { html: String::new(), asset_base };
+    writer.write_node(&tree, false);
+    Ok(writer.html)
+}
+
+// Renders markdown into an HTML fragment, LaTeX math is rendered as MathML.
+pub fn markdown_to_html(markdown: &str) -> Result<String> {
+    render(markdown, None)
+}
+
+// Same as `markdown_to_html`, but asset paths in the page are rewritten
+// to be relative to the graph root.
+pub fn page_markdown_to_html(
+    markdown: &str,
+    page_path: &Path,
+    graph_root: &Path,
+) -> Result<String> {
+    let page_dir = page_path.parent().unwrap_or(Path::new(""));
+    render(markdown, Some(AssetBase { page_dir, graph_root }))
+}
+
+pub fn html_document(title: &str, head: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n{}</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        head,
+        body
+    )
+}
@@ -0,0 +1,41 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+// Kitty limits each escape sequence to 4096 bytes of payload.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+// Displays the PNG with the kitty graphics protocol, scaled to `columns` cells wide.
+// `q=2` keeps the terminal from answering, which would otherwise arrive as key presses.
+pub fn encode_kitty(png_buf: &[u8], columns: u16) -> Vec<u8> {
+    let payload = STANDARD.encode(png_buf);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    let mut kitty = Vec::with_capacity(payload.len() + chunks.len() * 16);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        match i {
+            0 => kitty.extend(format!("\x1b_Ga=T,f=100,q=2,c={},m={};", columns, more).bytes()),
+            _ => kitty.extend(format!("\x1b_Gm={};", more).bytes()),
+        }
+        kitty.extend_from_slice(chunk);
+        kitty.extend_from_slice(b"\x1b\\");
+    }
+    // The cursor is left next to the image's last row.
+    kitty.push(b'\n');
+    kitty
+}
+
+// Deletes the images on screen, which kitty keeps when the screen is cleared.
+pub const KITTY_DELETE_IMAGES: &[u8] = b"\x1b_Ga=d,q=2\x1b\\";
+
+// Displays the PNG with iTerm's inline images protocol, scaled to `columns` cells wide.
+pub fn encode_iterm(png_buf: &[u8], columns: u16) -> Vec<u8> {
+    let mut iterm = format!(
+        "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:",
+        png_buf.len(),
+        columns
+    )
+    .into_bytes();
+    iterm.extend(STANDARD.encode(png_buf).bytes());
+    iterm.extend_from_slice(b"\x07\n");
+    iterm
+}
@@ -0,0 +1,63 @@
+//! Spaced Repetition System (SRS) cards embedded in Logseq pages.
+//!
+//! The `losrs` binary is a thin layer over this crate, other tools can use it
+//! to read cards from pages, schedule reviews and render cards.
+//!
+//! ```no_run
+//! use losrs::StorageManager;
+//! use losrs::settings::StorageSettings;
+//!
+//! # fn main() -> losrs::Result<()> {
+//! let storage_manager =
+//!     StorageManager::new("graph".as_ref(), &StorageSettings::default())?;
+//! for cm in storage_manager.select_card_metadata("graph".as_ref(), None)? {
+//!     println!("{} is due {}", cm.card_ref.prompt_fingerprint, cm.srs_meta.fsrs_meta.due);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod anki;
+pub mod ansi;
+mod assets;
+pub mod cache;
+pub mod calendar;
+pub mod csv_cards;
+pub mod error;
+pub mod html;
+mod inline_image;
+pub mod normalize;
+pub mod output;
+pub mod references;
+pub mod review;
+pub mod settings;
+pub mod sixel;
+pub mod stats;
+pub mod storage;
+pub mod terminal;
+mod terminal_probe;
+pub mod tui;
+pub mod types;
+pub mod typst_markup;
+pub mod typst_world;
+
+pub use error::Error;
+pub use error::RenderTool;
+pub use error::Result;
+pub use output::CardBodyParts;
+pub use output::render_card;
+pub use output::render_card_pngs;
+pub use review::compute_next_srs_meta;
+pub use storage::StorageManager;
+pub use storage::parse_page;
+pub use storage::parse_page_str;
+pub use types::Card;
+pub use types::CardBody;
+pub use types::CardId;
+pub use types::CardMetadata;
+pub use types::CardRef;
+pub use types::CardReviewLog;
+pub use types::FSRSMeta;
+pub use types::Fingerprint;
+pub use types::ReviewLog;
+pub use types::SRSMeta;
@@ -1,26 +1,45 @@
+// Modules only the binary needs, the library does not serve or prerender anything itself.
+mod prerender;
+mod rpc;
+mod web;
+
+use std::collections::BTreeSet;
 use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context;
 use anyhow::Result;
+use anyhow::anyhow;
 use chrono::DateTime;
 use chrono::FixedOffset;
 use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
 
-use crate::output::show_card;
-use crate::settings::Settings;
-use crate::storage::StorageManager;
-use crate::types::Card;
-use crate::types::CardId;
-use crate::types::Fingerprint;
+use losrs::anki;
+use losrs::cache;
+use losrs::calendar;
+use losrs::csv_cards;
+use losrs::html;
+use losrs::output;
+use losrs::output::CardBodyParts;
+use losrs::output::show_card;
+use losrs::review;
+use losrs::review::DueCardsFilter;
+use losrs::settings::OutputFormat;
+use losrs::settings::Settings;
+use losrs::stats;
+use losrs::storage::StorageManager;
+use losrs::terminal::SessionEndResponse;
+use losrs::terminal::grab_term_size;
+use losrs::tui::Progress;
+use losrs::tui::ReviewScreen;
+use losrs::types::Card;
+use losrs::types::CardId;
+use losrs::types::Fingerprint;
 
-pub mod output;
-pub mod review;
-pub mod settings;
-pub mod storage;
-pub mod terminal;
-pub mod types;
+use crate::prerender::Prerenderer;
 
 /// Work with Spaced Repetition System (SRS) cards embedded in Logseq pages
 #[derive(Parser)]
@@ -51,6 +70,38 @@ fn parse_datetime(src: &str) -> Result<DateTime<FixedOffset>> {
     Ok(DateTime::parse_from_rfc3339(src)?)
 }
 
+fn parse_duration(src: &str) -> Result<Duration> {
+    let too_large = || anyhow!("duration too large");
+    let mut duration = Duration::ZERO;
+    let mut digits = String::new();
+    for c in src.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit_secs = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            _ => return Err(anyhow!("unknown unit '{}', expected one of s, m, h", c)),
+        };
+        if digits.is_empty() {
+            return Err(anyhow!("expected a number before '{}'", c));
+        }
+        let secs = digits.parse::<u64>().ok().and_then(|n| n.checked_mul(unit_secs));
+        duration = secs
+            .and_then(|secs| duration.checked_add(Duration::from_secs(secs)))
+            .ok_or_else(too_large)?;
+        digits.clear();
+    }
+    if src.is_empty() || !digits.is_empty() {
+        return Err(anyhow!("expected a duration like 15m or 1h30m"));
+    }
+    // The session has to be able to tell when it runs out.
+    Instant::now().checked_add(duration).ok_or_else(too_large)?;
+    Ok(duration)
+}
+
 #[derive(Args)]
 struct CardRefArgs {
     /// The path to the page file or graph root directory
@@ -68,6 +119,11 @@ enum Commands {
     Show {
         #[command(flatten)]
         card_ref: CardRefArgs,
+
+        /// Wrap the cards in a complete HTML document.
+        /// Only used with the html output format.
+        #[arg(long)]
+        standalone: bool,
     },
     /// Review cards
     Review {
@@ -84,15 +140,93 @@ enum Commands {
         #[arg(long, value_parser = parse_datetime, value_name = "TIMESTAMP")]
         up_to: Option<DateTime<FixedOffset>>,
 
-        /// Seed used for shuffling cards ready to be reviewed
+        /// Seed used for shuffling cards ready to be reviewed,
+        /// otherwise the cards due the longest are reviewed first
         #[arg(long)]
         seed: Option<u64>,
+
+        /// Override `review.new_cards_per_day` for this session
+        #[arg(long, value_name = "COUNT")]
+        new_cards_per_day: Option<u32>,
+
+        /// Override `review.max_reviews_per_day` for this session
+        #[arg(long, value_name = "COUNT")]
+        max_reviews_per_day: Option<u32>,
+
+        /// Stop the session after reviewing this many cards
+        #[arg(long, value_name = "COUNT")]
+        limit: Option<usize>,
+
+        /// Stop the session after this much time has passed, like 15m or 1h30m.
+        /// The card being reviewed when time runs out is finished first.
+        #[arg(long, value_parser = parse_duration, value_name = "DURATION")]
+        time: Option<Duration>,
     },
     /// Print metadata for cards
     Metadata {
         #[command(flatten)]
         card_ref: CardRefArgs,
     },
+    /// Print review statistics
+    Stats {
+        /// The path to the page file or graph root directory
+        path: PathBuf,
+
+        /// RFC3999 timestamp to compute statistics at
+        #[arg(long, value_parser = parse_datetime, value_name = "TIMESTAMP")]
+        at: Option<DateTime<FixedOffset>>,
+
+        /// Print statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Draw a calendar of past reviews and upcoming due cards
+    Calendar {
+        /// The path to the page file or graph root directory
+        path: PathBuf,
+
+        /// RFC3999 timestamp to draw the calendar at
+        #[arg(long, value_parser = parse_datetime, value_name = "TIMESTAMP")]
+        at: Option<DateTime<FixedOffset>>,
+    },
+    /// Export cards to other spaced repetition tools
+    Export {
+        #[command(subcommand)]
+        command: ExportCommands,
+    },
+    /// Import cards from other spaced repetition tools
+    Import {
+        #[command(subcommand)]
+        command: ImportCommands,
+    },
+    /// Serve editor integrations over JSON-RPC
+    #[command(after_help = include_str!("../docs/rpc.md"))]
+    Serve {
+        /// The path to the page file or graph root directory
+        path: PathBuf,
+
+        /// Read requests from stdin and write responses to stdout, one per line
+        #[arg(long, required = true)]
+        stdio: bool,
+    },
+    /// Serve a review interface for web browsers
+    Web {
+        /// The path to the page file or graph root directory
+        path: PathBuf,
+
+        /// The port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// The address to listen on.
+        /// Use 0.0.0.0 to review from other devices on the local network.
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+
+        /// RFC3999 timestamp to use as the time of the reviews
+        #[arg(long, value_parser = parse_datetime, value_name = "TIMESTAMP")]
+        at: Option<DateTime<FixedOffset>>,
+    },
     /// Fix metadata for cards
     FixMetadata {
         #[command(flatten)]
@@ -104,6 +238,11 @@ enum Commands {
         #[command(subcommand)]
         command: ConfigCommands,
     },
+    /// Manage the cache of rendered card images
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
 }
 
 #[derive(Subcommand)]
@@ -114,24 +253,109 @@ enum ConfigCommands {
     Path,
 }
 
-fn shuffle_slice<T>(s: &mut [T], seed: u64) {
-    use rand::SeedableRng;
-    use rand::rngs::SmallRng;
-    use rand::seq::SliceRandom;
-    let mut rng = SmallRng::seed_from_u64(seed);
-    s.shuffle(&mut rng);
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Remove all cached images
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ExportCommands {
+    /// Export cards and their schedules to an Anki package (.apkg),
+    /// each page becomes a subdeck
+    Anki {
+        /// The path to the page file or graph root directory
+        path: PathBuf,
+
+        /// The path to write the package to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Name of the top level deck
+        #[arg(long, default_value = "losrs")]
+        deck: String,
+    },
+    /// Export cards and their metadata as CSV, one card per row
+    Csv {
+        /// The path to the page file or graph root directory
+        path: PathBuf,
+
+        /// The path to write to, stdout by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Separate columns with tabs instead of commas
+        #[arg(long)]
+        tsv: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportCommands {
+    /// Import notes and their review history from an Anki package (.apkg),
+    /// each deck becomes a page
+    Anki {
+        /// The path to the package
+        package: PathBuf,
+
+        /// The graph root directory to import into, created if missing
+        graph_root: PathBuf,
+    },
+    /// Add cards to a page from CSV with question and answer columns
+    Csv {
+        /// The path to the CSV file
+        file: PathBuf,
+
+        /// The page file to add the cards to, created if missing
+        page: PathBuf,
+
+        /// Columns are separated with tabs instead of commas
+        #[arg(long)]
+        tsv: bool,
+
+        /// Skip the first row
+        #[arg(long)]
+        header: bool,
+    },
+}
+
+enum SessionEnd {
+    AllReviewed,
+    DailyLimitsReached { left_due: usize },
+    SessionLimitReached { left_due: usize },
+}
+
+impl std::fmt::Display for SessionEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionEnd::AllReviewed => write!(f, "Reviewed all cards, huzzah!"),
+            SessionEnd::DailyLimitsReached { left_due } => {
+                write!(f, "Reached the daily limits, due cards left for later: {}", left_due)
+            }
+            SessionEnd::SessionLimitReached { left_due } => {
+                write!(f, "Reached the session limit, cards still due: {}", left_due)
+            }
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let settings = Settings::new(cli.config)?;
+    let mut settings = Settings::new(cli.config)?;
 
     match cli.command {
-        Commands::Show { card_ref: CardRefArgs { path, card_id } } => {
+        Commands::Show { card_ref: CardRefArgs { path, card_id }, standalone } => {
+            output::resolve_auto_settings(&mut settings.output);
+            if standalone && !matches!(settings.output.format, OutputFormat::Html) {
+                return Err(anyhow!("--standalone can only be used with the html output format"));
+            }
             let storage_manager = StorageManager::new(&path, &settings.storage)?;
             let mut card_metas = storage_manager.select_card_metadata(&path, card_id)?;
             card_metas.sort_by(|a, b| a.card_ref.source_path.cmp(&b.card_ref.source_path));
+            let mut standalone_body = String::new();
+            let mut shown_pages = BTreeSet::new();
             for cm in card_metas {
+                shown_pages.insert(cm.card_ref.source_path.clone());
                 let card_body =
                     storage_manager.load_card_body_by_ref(&cm.card_ref).with_context(|| {
                         format!(
@@ -140,31 +364,154 @@ fn main() -> Result<()> {
                             cm.card_ref.source_path.display(),
                         )
                     })?;
-                show_card(&Card { metadata: cm, body: card_body }, &settings.output)?
+                let card = Card { metadata: cm, body: card_body };
+                if standalone {
+                    let html = output::render_card(&card, CardBodyParts::ALL, &settings.output)?;
+                    standalone_body.push_str(&String::from_utf8(html)?);
+                } else {
+                    show_card(&card, &settings.output)?
+                }
+            }
+            if standalone {
+                let style = format!("<style>\n{}</style>\n", html::STYLE);
+                print!("{}", html::html_document("losrs", &style, &standalone_body));
+            }
+            // Rendering images is slow, so the rest of the page is rendered ahead of reviewing it.
+            if settings.output.format.is_image() {
+                for page in shown_pages {
+                    for cm in storage_manager.select_card_metadata(&page, None)? {
+                        let card_body = storage_manager.load_card_body_by_ref(&cm.card_ref)?;
+                        output::warm_image_cache(
+                            &Card { metadata: cm, body: card_body },
+                            &settings.output,
+                            grab_term_size(),
+                        )?;
+                    }
+                }
             }
         }
-        Commands::Review { card_ref: CardRefArgs { path, card_id }, at, up_to, seed } => {
+        Commands::Review {
+            card_ref: CardRefArgs { path, card_id },
+            at,
+            up_to,
+            seed,
+            new_cards_per_day,
+            max_reviews_per_day,
+            limit,
+            time,
+        } => {
+            output::resolve_auto_settings(&mut settings.output);
             let mut storage_manager = StorageManager::new(&path, &settings.storage)?;
-            let now = chrono::offset::Utc::now().fixed_offset();
-            let (at, up_to) = match (at, up_to) {
-                (None, None) => (now, now),
-                (None, Some(up_to)) => (now, up_to),
-                (Some(at), None) => (at, at),
-                (Some(at), Some(up_to)) => (at, up_to),
-            };
-            let mut card_metas = storage_manager.select_card_metadata(&path, card_id)?;
-            match (|| -> Result<()> {
-                card_metas.retain(|cm| cm.srs_meta.logseq_srs_meta.next_schedule <= up_to);
-                shuffle_slice(&mut card_metas, seed.unwrap_or_default());
-                for cm in card_metas {
-                    review::review_card(&cm, at, &settings.output, &mut storage_manager)?
+            let at = at.unwrap_or_else(|| chrono::offset::Utc::now().fixed_offset());
+            let mut review_settings = settings.review.clone();
+            if let Some(new_cards_per_day) = new_cards_per_day {
+                review_settings.new_cards_per_day = new_cards_per_day;
+            }
+            if let Some(max_reviews_per_day) = max_reviews_per_day {
+                review_settings.max_reviews_per_day = max_reviews_per_day;
+            }
+            let session_start = Instant::now();
+            match (|| -> Result<SessionEnd> {
+                let filter = DueCardsFilter { card_id, up_to, shuffle_seed: seed };
+                let (mut card_metas, held_back) = review::select_due_cards(
+                    &path,
+                    &storage_manager,
+                    &review_settings,
+                    at,
+                    filter,
+                )?;
+                let prerender_cards = review_settings.prerender_cards as usize;
+                let prerenderer = (settings.output.format.is_image() && prerender_cards > 0)
+                    .then(|| Prerenderer::new(&settings.output, prerender_cards));
+                // Cards before this one have been handed to the prerenderer already.
+                let mut prerendered = 0;
+                // Answers are undone in reverse order,
+                // so the last answer is always for the card right before the current one.
+                let mut answered_reviews: Vec<review::AnsweredReview> = Vec::new();
+                let mut reviewed = 0;
+                // Session limits don't stop the review of cards whose answers were undone.
+                let mut furthest = 0;
+                // Dropped before the session end is printed, restoring the terminal.
+                let mut screen = ReviewScreen::new(session_start);
+                loop {
+                    let left_due = card_metas.len() - reviewed + held_back;
+                    let out_of_cards = limit.is_some_and(|limit| reviewed >= limit);
+                    let out_of_time = time.is_some_and(|time| session_start.elapsed() >= time);
+                    let session_end = if reviewed == card_metas.len() {
+                        match held_back {
+                            0 => Some(SessionEnd::AllReviewed),
+                            left_due => Some(SessionEnd::DailyLimitsReached { left_due }),
+                        }
+                    } else if (out_of_cards || out_of_time) && reviewed >= furthest {
+                        Some(SessionEnd::SessionLimitReached { left_due })
+                    } else {
+                        None
+                    };
+                    if let Some(session_end) = session_end {
+                        // The last answer can still be undone before the session ends.
+                        let Some(answered_review) = answered_reviews.pop() else {
+                            return Ok(session_end);
+                        };
+                        screen.set_progress(Progress {
+                            current: reviewed,
+                            total: card_metas.len(),
+                            due_left: left_due,
+                        });
+                        match screen.wait_for_session_end(&session_end.to_string())? {
+                            SessionEndResponse::Finish => return Ok(session_end),
+                            SessionEndResponse::UndoPrevious => {
+                                reviewed -= 1;
+                                card_metas[reviewed] =
+                                    review::undo_review(answered_review, &mut storage_manager)?;
+                                continue;
+                            }
+                        }
+                    }
+                    if let Some(prerenderer) = &prerenderer {
+                        let upcoming = (reviewed + 1 + prerender_cards).min(card_metas.len());
+                        // After undoing an answer the upcoming cards have been handed over already.
+                        let start = prerendered.max(reviewed + 1).min(upcoming);
+                        for cm in &card_metas[start..upcoming] {
+                            let card_body = storage_manager.load_card_body_by_ref(&cm.card_ref)?;
+                            let card = Card { metadata: cm.clone(), body: card_body };
+                            prerenderer.submit(card, screen.card_size());
+                        }
+                        prerendered = prerendered.max(upcoming);
+                    }
+                    screen.set_progress(Progress {
+                        current: reviewed + 1,
+                        total: card_metas.len(),
+                        due_left: left_due,
+                    });
+                    let cm = &card_metas[reviewed];
+                    match review::review_card(
+                        cm,
+                        at,
+                        &settings.output,
+                        &mut storage_manager,
+                        &mut screen,
+                    )? {
+                        review::ReviewOutcome::Answered(answered_review) => {
+                            answered_reviews.push(answered_review);
+                            reviewed += 1;
+                            furthest = furthest.max(reviewed);
+                        }
+                        review::ReviewOutcome::UndoRequested => {
+                            let Some(answered_review) = answered_reviews.pop() else {
+                                screen.set_notice("Nothing to undo yet");
+                                continue;
+                            };
+                            reviewed -= 1;
+                            card_metas[reviewed] =
+                                review::undo_review(answered_review, &mut storage_manager)?;
+                        }
+                    }
                 }
-                Ok(())
             })() {
-                Ok(_) => println!("Reviewed all cards, huzzah!"),
-                Err(err) => match err.downcast_ref::<terminal::NopeOutError>() {
-                    Some(e) => println!("{}", e),
-                    None => Err(err)?,
+                Ok(session_end) => println!("{}", session_end),
+                Err(err) => match err.downcast_ref::<losrs::Error>() {
+                    Some(e @ losrs::Error::NopeOut) => println!("{}", e),
+                    _ => Err(err)?,
                 },
             }
         }
@@ -175,6 +522,99 @@ fn main() -> Result<()> {
                 output::show_metadata(&cm)?;
             }
         }
+        Commands::Stats { path, at, json } => {
+            let storage_manager = StorageManager::new(&path, &settings.storage)?;
+            let at = at.unwrap_or_else(|| chrono::offset::Local::now().fixed_offset());
+            let card_metas = storage_manager.select_card_metadata(&path, None)?;
+            let card_review_logs = storage_manager.load_review_logs()?;
+            let stats_report =
+                stats::StatsReport::new(&card_metas, card_review_logs.as_deref(), at);
+            stats::show_stats(&stats_report, json)?;
+        }
+        Commands::Calendar { path, at } => {
+            let storage_manager = StorageManager::new(&path, &settings.storage)?;
+            let at = at.unwrap_or_else(|| chrono::offset::Local::now().fixed_offset());
+            let card_metas = storage_manager.select_card_metadata(&path, None)?;
+            let card_review_logs = storage_manager.load_review_logs()?;
+            calendar::show_calendar(&card_metas, card_review_logs.as_deref(), at)?;
+        }
+        Commands::Export { command } => match command {
+            ExportCommands::Anki { path, output, deck } => {
+                let storage_manager = StorageManager::new(&path, &settings.storage)?;
+                let summary = anki::export_anki(&storage_manager, &path, &output, &deck)?;
+                println!(
+                    "Exported {} cards in {} decks with {} media files to {}",
+                    summary.cards,
+                    summary.decks,
+                    summary.media_files,
+                    output.display()
+                );
+            }
+            ExportCommands::Csv { path, output, tsv } => {
+                let storage_manager = StorageManager::new(&path, &settings.storage)?;
+                match output {
+                    Some(output) => {
+                        let file = std::fs::File::create(&output).with_context(|| {
+                            format!("when creating {} for writing", output.display())
+                        })?;
+                        let cards = csv_cards::export_csv(&storage_manager, &path, file, tsv)?;
+                        println!("Exported {} cards to {}", cards, output.display());
+                    }
+                    None => {
+                        csv_cards::export_csv(&storage_manager, &path, std::io::stdout(), tsv)?;
+                    }
+                }
+            }
+        },
+        Commands::Import { command } => match command {
+            ImportCommands::Anki { package, graph_root } => {
+                // [ref:logseq-dir-layout]
+                std::fs::create_dir_all(graph_root.join("pages")).with_context(|| {
+                    format!("when creating pages directory in {}", graph_root.display())
+                })?;
+                let mut storage_manager = StorageManager::new(&graph_root, &settings.storage)?;
+                let summary = anki::import_anki(&mut storage_manager, &package, &graph_root)?;
+                println!(
+                    "Imported {} cards into {} pages with {} media files",
+                    summary.cards, summary.pages, summary.media_files
+                );
+            }
+            ImportCommands::Csv { file, page, tsv, header } => {
+                let cards = csv_cards::read_csv(&file, &page, tsv, header)?;
+                // Storage is located relative to an existing page, start with an empty one.
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&page)
+                    .with_context(|| format!("when creating {}", page.display()))?;
+                let mut storage_manager = StorageManager::new(&page, &settings.storage)?;
+                let cards = csv_cards::import_csv(&mut storage_manager, &page, cards)?;
+                println!("Imported {} cards into {}", cards, page.display());
+            }
+        },
+        Commands::Serve { path, stdio: _ } => {
+            let mut storage_manager = StorageManager::new(&path, &settings.storage)?;
+            rpc::serve(
+                &path,
+                &mut storage_manager,
+                &settings.review,
+                std::io::stdin().lock(),
+                std::io::stdout().lock(),
+            )?;
+        }
+        Commands::Web { path, port, host, at } => {
+            let mut storage_manager = StorageManager::new(&path, &settings.storage)?;
+            let server = web::bind(&host, port)?;
+            println!("Reviewing at http://{}/", server.server_addr());
+            web::serve(
+                server,
+                &path,
+                &mut storage_manager,
+                &settings.review,
+                &settings.output,
+                at,
+            )?;
+        }
         Commands::FixMetadata { card_ref: CardRefArgs { path, card_id } } => {
             let mut storage_manager = StorageManager::new(&path, &settings.storage)?;
             let card_metas = storage_manager.select_card_metadata(&path, card_id)?;
@@ -191,6 +631,16 @@ fn main() -> Result<()> {
                 println!("{}", Settings::get_config_path()?.display());
             }
         },
+        Commands::Cache { command } => match command {
+            CacheCommands::Clear => {
+                let removed = cache::clear()?;
+                println!(
+                    "Removed {} cached images from {}",
+                    removed,
+                    cache::cache_dir()?.display()
+                );
+            }
+        },
     }
 
     Ok(())
@@ -0,0 +1,81 @@
+use std::sync::LazyLock;
+
+use regex::Captures;
+use regex::Regex;
+
+// Unlike when parsing pages, tags like `#cards` are not mistaken for the card tag.
+static CARD_TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#card( <!-- CSN:[0-9]+ -->)?(?<end>\s|$)").unwrap());
+static PROPERTY_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_-]+::( |$)").unwrap());
+// Inline code comes first, so that nothing within it is matched.
+static MARKUP_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?<code>`[^`]*`)",
+        r"|\[(?<label>[^\[\]]+)\]\((?:\[\[[^\[\]]+\]\]|\(\([0-9a-fA-F-]{36}\)\))\)",
+        r"|(?<before>^|\s)#(?:\[\[(?<tag_page>[^\[\]]+)\]\]|(?<tag>[^\s,.!?;:()\[\]{}#`]+))",
+        r"|\[\[(?<page>[^\[\]]+)\]\]",
+        r"|\{\{cloze (?<cloze>.*?)\}\}",
+        r"|\{\{(?<macro>[a-z-]+)\b.*?\}\} ?",
+    ))
+    .unwrap()
+});
+
+// Block properties are written as `key:: value` lines below the first line of the block.
+pub(crate) fn is_property(line: &str) -> bool {
+    PROPERTY_RE.is_match(line.trim())
+}
+
+fn normalize_markup(caps: &Captures) -> String {
+    if let Some(label) = caps.name("label") {
+        return format!("*{}*", label.as_str());
+    }
+    if let Some(tag) = caps.name("tag_page").or(caps.name("tag")) {
+        return format!("{}*#{}*", &caps["before"], tag.as_str());
+    }
+    if let Some(page) = caps.name("page") {
+        return format!("*{}*", page.as_str());
+    }
+    if let Some(cloze) = caps.name("cloze") {
+        return cloze.as_str().to_owned();
+    }
+    match caps.name("macro").map(|m| m.as_str()) {
+        // Embeds that were not resolved are shown as written, like block references.
+        Some("embed") => caps[0].to_owned(),
+        Some(_) => String::new(),
+        None => caps[0].to_owned(),
+    }
+}
+
+// Hides what only matters to Logseq from cards as they are displayed:
+// the `#card` tag with the serial number, block properties like `id::` or `collapsed::`
+// and macros like `{{query ...}}`, while the text of `{{cloze ...}}` is kept.
+// Page links, aliased links and tags are shown as emphasized page names.
+// Code is left as it is.
+pub fn normalize_for_display(markdown: &str) -> String {
+    let mut in_code_block = false;
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        // Code blocks often start right after the list marker.
+        let is_fence = trimmed.strip_prefix("- ").unwrap_or(trimmed).starts_with("```");
+        if is_fence {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block || is_fence {
+            lines.push(line.to_owned());
+            continue;
+        }
+        if is_property(trimmed) {
+            continue;
+        }
+        let line = CARD_TAG_RE.replace_all(line, "$end");
+        // The tag can be on a line of its own, below a multi-line prompt.
+        if line.trim().is_empty() && !trimmed.is_empty() {
+            continue;
+        }
+        let line = MARKUP_RE.replace_all(&line, normalize_markup);
+        lines.push(line.trim_end().to_owned());
+    }
+    lines.join("\n")
+}
@@ -1,23 +1,41 @@
+use std::borrow::Cow;
 use std::fmt::Display;
-use std::io::Read;
 use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process;
 
-use anyhow::Context;
-use anyhow::Result;
-use anyhow::anyhow;
 use serde::Serialize;
-use tempfile::NamedTempFile;
 
+use crate::ansi::markdown_to_ansi;
+use crate::assets::AssetLookup;
+use crate::assets::find_asset;
+use crate::assets::rewrite_urls;
+use crate::cache;
+use crate::error::Error;
+use crate::error::RenderTool;
+use crate::error::Result;
+use crate::html::markdown_to_html;
+use crate::html::page_markdown_to_html;
+use crate::inline_image::encode_iterm;
+use crate::inline_image::encode_kitty;
+use crate::normalize::normalize_for_display;
 use crate::settings::OutputFormat;
 use crate::settings::OutputSettings;
+use crate::settings::Theme;
+use crate::sixel::encode_sixel;
+use crate::storage::find_graph_root;
+use crate::storage::normalize_path;
 use crate::terminal::grab_term_size;
+use crate::terminal_probe::probe_terminal;
 use crate::types::Card;
 use crate::types::CardMetadata;
 use crate::types::CardRef;
 use crate::types::FSRSMeta;
 use crate::types::SRSMeta;
+use crate::typst_markup;
+use crate::typst_world::CardWorld;
+use crate::typst_world::document_to_pngs;
 
 bitflags::bitflags! {
     pub struct CardBodyParts: u8 {
@@ -28,23 +46,125 @@ bitflags::bitflags! {
     }
 }
 
-fn show_card_inner(
+fn render_error(tool: RenderTool, message: impl Display) -> Error {
+    Error::Render { tool, message: message.to_string() }
+}
+
+// Renders the card into a buffer in the configured output format.
+pub fn render_card(
     card: &Card,
     card_body_parts: CardBodyParts,
     output_settings: &OutputSettings,
-) -> Result<()> {
+) -> Result<Vec<u8>> {
     let mut result = Vec::new();
     match output_settings.format {
-        OutputFormat::Clean => format_card_clean(card, &mut result, card_body_parts)?,
-        OutputFormat::Typst => format_card_typst(card, &mut result, card_body_parts)?,
+        OutputFormat::Auto => {
+            let mut output_settings = output_settings.clone();
+            resolve_auto_settings(&mut output_settings);
+            return render_card(card, card_body_parts, &output_settings);
+        }
+        OutputFormat::Clean => {
+            format_card_clean(card, &mut result, card_body_parts, output_settings)?
+        }
+        OutputFormat::Typst => {
+            format_card_typst(card, &mut result, card_body_parts, output_settings)?
+        }
         OutputFormat::Logseq => format_card_logseq(card, &mut result, card_body_parts)?,
-        OutputFormat::Sixel => {
-            format_card_sixel(card, &mut result, card_body_parts, output_settings)?
+        OutputFormat::Html => {
+            format_card_html(card, &mut result, card_body_parts, output_settings)?
+        }
+        OutputFormat::Ansi => {
+            format_card_ansi(card, &mut result, card_body_parts, output_settings)?
+        }
+        OutputFormat::Sixel | OutputFormat::Kitty | OutputFormat::ITerm => {
+            format_card_image(card, &mut result, card_body_parts, output_settings)?
+        }
+    };
+    Ok(result)
+}
+
+// Renders the card into PNG images sized after the terminal,
+// one per page when the card does not fit in the terminal.
+pub fn render_card_pngs(
+    card: &Card,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+) -> Result<Vec<Vec<u8>>> {
+    card_to_pngs(card, card_body_parts, output_settings, grab_term_size())
+}
+
+// A card rendered for the terminal,
+// image formats split cards that do not fit in the terminal into pages.
+pub struct RenderedCard {
+    format: OutputFormat,
+    // Kitty and iTerm pages are kept as PNGs, to be scaled to the width they are shown at,
+    // other pages are what is written to the terminal.
+    pages: Vec<Vec<u8>>,
+    // (columns, lines) the pages of image formats are laid out for.
+    term_size: (u16, u16),
+}
+
+impl RenderedCard {
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn format(&self) -> &OutputFormat {
+        &self.format
+    }
+
+    pub fn term_size(&self) -> (u16, u16) {
+        self.term_size
+    }
+
+    // The page as written to the terminal, kitty and iTerm scale images to `columns` cells wide.
+    pub fn page(&self, page: usize, columns: u16) -> Cow<'_, [u8]> {
+        let page = &self.pages[page];
+        match self.format {
+            OutputFormat::Kitty => Cow::Owned(encode_kitty(page, columns)),
+            OutputFormat::ITerm => Cow::Owned(encode_iterm(page, columns)),
+            _ => Cow::Borrowed(page),
         }
-        OutputFormat::Kitty => show_card_kitty_or_iterm(card, card_body_parts, output_settings)?,
-        OutputFormat::ITerm => show_card_kitty_or_iterm(card, card_body_parts, output_settings)?,
+    }
+
+    pub fn show_page(&self, page: usize) -> Result<()> {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&self.page(page, grab_term_size().0))?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+// Images are laid out for `term_size`, the part of the terminal they are shown in.
+pub fn render_card_for_terminal(
+    card: &Card,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+    term_size: (u16, u16),
+) -> Result<RenderedCard> {
+    let pages = match output_settings.format {
+        OutputFormat::Sixel => card_to_pngs(card, card_body_parts, output_settings, term_size)?
+            .iter()
+            .map(|png_buf| png_to_sixel(png_buf))
+            .collect::<Result<_>>()?,
+        OutputFormat::Kitty | OutputFormat::ITerm => {
+            card_to_pngs(card, card_body_parts, output_settings, term_size)?
+        }
+        _ => vec![render_card(card, card_body_parts, output_settings)?],
     };
-    std::io::stdout().write_all(&result)?;
+    Ok(RenderedCard { format: output_settings.format.clone(), pages, term_size })
+}
+
+fn show_card_inner(
+    card: &Card,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+) -> Result<()> {
+    let rendered =
+        render_card_for_terminal(card, card_body_parts, output_settings, grab_term_size())?;
+    for page in 0..rendered.page_count() {
+        rendered.show_page(page)?;
+    }
     Ok(())
 }
 
@@ -60,18 +180,42 @@ pub fn show_card_prompt(card: &Card, output_settings: &OutputSettings) -> Result
 struct ShowCardMetadata {
     card_ref: CardRef,
     fsrs_meta: FSRSMeta,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    suspended: bool,
 }
 
 pub fn show_metadata(cm: &CardMetadata) -> Result<()> {
     let cm = ShowCardMetadata {
         card_ref: cm.card_ref.clone(),
         fsrs_meta: cm.srs_meta.fsrs_meta.clone(),
+        suspended: cm.srs_meta.suspended,
     };
     println!("{}", serde_json::to_string_pretty(&cm)?);
     Ok(())
 }
 
-fn card_to_markdown(card: &Card, card_body_parts: CardBodyParts) -> String {
+// Block references and embeds are resolved when the graph was indexed,
+// see `GraphIndex::resolve`, then what only matters to Logseq is hidden,
+// see `normalize_for_display`.
+// The `logseq` format shows cards as they are stored instead.
+pub fn display_markdown(
+    cm: &CardMetadata,
+    markdown: &str,
+    output_settings: &OutputSettings,
+) -> String {
+    match &cm.graph_index {
+        Some(graph_index) => normalize_for_display(
+            &graph_index.resolve(markdown, output_settings.max_reference_depth),
+        ),
+        None => normalize_for_display(markdown),
+    }
+}
+
+fn card_to_markdown(
+    card: &Card,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+) -> String {
     let mut parts = Vec::new();
     if card_body_parts.contains(CardBodyParts::PROMPT) {
         parts.push(card.body.prompt.as_str());
@@ -79,15 +223,16 @@ fn card_to_markdown(card: &Card, card_body_parts: CardBodyParts) -> String {
     if card_body_parts.contains(CardBodyParts::RESPONSE) {
         parts.push(card.body.response.as_str());
     }
-    parts.join("\n")
+    display_markdown(&card.metadata, &parts.join("\n"), output_settings)
 }
 
 pub fn format_card_clean(
     card: &Card,
     mut writer: impl std::io::Write,
     card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
 ) -> Result<()> {
-    writeln!(writer, "{}", card_to_markdown(card, card_body_parts))?;
+    writeln!(writer, "{}", card_to_markdown(card, card_body_parts, output_settings))?;
     Ok(())
 }
 
@@ -95,74 +240,164 @@ pub fn format_card_typst(
     card: &Card,
     mut writer: impl std::io::Write,
     card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
 ) -> Result<()> {
-    let markdown = card_to_markdown(card, card_body_parts);
-    let typst = markdown_to_typst(markdown)
-        .with_context(|| "failed to convert markdown to typst using pandoc".to_owned())?;
+    let markdown = card_to_markdown(card, card_body_parts, output_settings);
+    let typst = markdown_to_typst(markdown)?;
     write!(writer, "{}", typst)?;
     Ok(())
 }
 
-fn card_to_png(
+// Styled for terminals that can not show images, wrapped to the terminal's width.
+pub fn format_card_ansi(
     card: &Card,
+    mut writer: impl std::io::Write,
     card_body_parts: CardBodyParts,
     output_settings: &OutputSettings,
-) -> Result<Vec<u8>> {
-    let markdown = card_to_markdown(card, card_body_parts);
-    let typst = markdown_to_typst(markdown)
-        .with_context(|| "failed to convert markdown to typst using pandoc".to_owned())?;
+) -> Result<()> {
+    let markdown = card_to_markdown(card, card_body_parts, output_settings);
+    let (columns, _) = grab_term_size();
+    write!(writer, "{}", markdown_to_ansi(&markdown, columns.into())?)?;
+    Ok(())
+}
+
+// Each card is an HTML fragment of its own, see `html_document` to make a page out of them.
+pub fn format_card_html(
+    card: &Card,
+    mut writer: impl std::io::Write,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+) -> Result<()> {
+    let markdown = card_to_markdown(card, card_body_parts, output_settings);
+    let source_path = card.metadata.card_ref.source_path.as_path();
+    let html = match find_graph_root(source_path)? {
+        Some(graph_root) => page_markdown_to_html(&markdown, source_path, &graph_root)?,
+        None => markdown_to_html(&markdown)?,
+    };
+    writeln!(writer, "<div class=\"card\">{}</div>", html)?;
+    Ok(())
+}
+
+fn card_to_pngs(
+    card: &Card,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+    term_size: (u16, u16),
+) -> Result<Vec<Vec<u8>>> {
+    let card_body_parts_bits = card_body_parts.bits();
+    let markdown = card_to_markdown(card, card_body_parts, output_settings);
 
     // As per [ref:logseq-dir-layout]
     // the page file is located at `.../graph_root/pages/page.md`,
     // so to get graph_root we need to go up twice
     let graph_root =
-        card.metadata.card_ref.source_path.parent().and_then(Path::parent).ok_or(anyhow!(
-            "page file does not have a grandparent. The page is {}",
-            card.metadata.card_ref.source_path.display()
-        ))?;
-
-    let png_buf = typst_to_png(typst, graph_root, output_settings)
-        .with_context(|| "failed to convert typst to png via typst cli".to_owned())?;
+        card.metadata.card_ref.source_path.parent().and_then(Path::parent).ok_or_else(|| {
+            render_error(
+                RenderTool::Typst,
+                format!(
+                    "page file does not have a grandparent. The page is {}",
+                    card.metadata.card_ref.source_path.display()
+                ),
+            )
+        })?;
+
+    let (markdown, assets) = rewrite_image_urls(card, &markdown, graph_root)?;
+
+    let key = cache::image_key(
+        &markdown,
+        card_body_parts_bits,
+        output_settings,
+        term_size,
+        graph_root,
+        &assets,
+    )?;
+    // The cache only saves time, a cache that can't be read or written is rendered around.
+    if let Ok(Some(pages)) = cache::load_images(&key) {
+        return Ok(pages);
+    }
+    let typst = markdown_to_typst(markdown)?;
+    let pages = typst_to_pngs(typst, graph_root, output_settings, term_size)?;
+    let _ = cache::store_images(&key, &pages);
+    Ok(pages)
+}
 
-    Ok(png_buf)
+// The card is compiled in graph_root, so images are rewritten to be relative to it.
+// Returns the rewritten markdown and the assets it shows.
+fn rewrite_image_urls(
+    card: &Card,
+    markdown: &str,
+    graph_root: &Path,
+) -> Result<(String, Vec<PathBuf>)> {
+    let page = card.metadata.card_ref.source_path.as_path();
+    let page_dir = page.parent().unwrap_or(graph_root);
+    let root = normalize_path(graph_root);
+    let mut assets = Vec::new();
+    let rewritten = rewrite_urls(markdown, false, |url| {
+        let path = match find_asset(url, page_dir, graph_root) {
+            AssetLookup::NotAsset => return Ok(None),
+            AssetLookup::Found(path) => path,
+            AssetLookup::Missing(asset) => {
+                return Err(Error::MissingAsset {
+                    page: page.to_path_buf(),
+                    prompt: card.body.prompt_text().lines().next().unwrap_or_default().to_owned(),
+                    url: url.to_owned(),
+                    asset,
+                });
+            }
+        };
+        assets.push(path.clone());
+        // Assets outside of graph_root can not be read by Typst, which reports them itself.
+        let Ok(relative) = normalize_path(&path).strip_prefix(&root).map(Path::to_owned) else {
+            return Ok(None);
+        };
+        let relative =
+            relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>();
+        Ok(Some(relative.join("/")))
+    })?;
+    Ok((rewritten, assets))
 }
 
-pub fn format_card_sixel(
+// Renders the prompt alone and the whole card into the image cache,
+// the two ways a card is shown during review.
+pub fn warm_image_cache(
     card: &Card,
-    mut writer: impl std::io::Write,
-    card_body_parts: CardBodyParts,
     output_settings: &OutputSettings,
+    term_size: (u16, u16),
 ) -> Result<()> {
-    let png_buf = card_to_png(card, card_body_parts, output_settings)?;
-    let sixel_buf = png_to_sixel(png_buf)
-        .with_context(|| "failed to convert png to sixel via img2sixel cli".to_owned())?;
-    writer.write_all(&sixel_buf)?;
-
+    card_to_pngs(card, CardBodyParts::PROMPT, output_settings, term_size)?;
+    card_to_pngs(card, CardBodyParts::ALL, output_settings, term_size)?;
     Ok(())
 }
 
-pub fn show_card_kitty_or_iterm(
+// Encodes each page in the terminal's image protocol,
+// kitty and iTerm scale them to the terminal's width.
+fn card_to_terminal_images(
     card: &Card,
     card_body_parts: CardBodyParts,
     output_settings: &OutputSettings,
-) -> Result<()> {
-    use image::ImageReader;
-    use std::io::Cursor;
-
-    let png_buf = card_to_png(card, card_body_parts, output_settings)?;
-
-    let img = ImageReader::with_format(Cursor::new(png_buf), image::ImageFormat::Png).decode()?;
-
-    let conf = viuer::Config {
-        absolute_offset: false,
-        use_kitty: true,
-        use_iterm: true,
-        // TODO: figure out how to make sixel via viuer look as good as sixel via img2sixel
-        // use_sixel: false,
-        ..Default::default()
-    };
+) -> Result<Vec<Vec<u8>>> {
+    let term_size = grab_term_size();
+    let columns = term_size.0;
+    card_to_pngs(card, card_body_parts, output_settings, term_size)?
+        .iter()
+        .map(|png_buf| match output_settings.format {
+            OutputFormat::Sixel => png_to_sixel(png_buf),
+            OutputFormat::Kitty => Ok(encode_kitty(png_buf, columns)),
+            OutputFormat::ITerm => Ok(encode_iterm(png_buf, columns)),
+            _ => Err(Error::UnsupportedFormat(output_settings.format.clone())),
+        })
+        .collect()
+}
 
-    viuer::print(&img, &conf).unwrap();
+pub fn format_card_image(
+    card: &Card,
+    mut writer: impl std::io::Write,
+    card_body_parts: CardBodyParts,
+    output_settings: &OutputSettings,
+) -> Result<()> {
+    for image in card_to_terminal_images(card, card_body_parts, output_settings)? {
+        writer.write_all(&image)?;
+    }
 
     Ok(())
 }
@@ -177,31 +412,60 @@ impl Display for Typst {
     }
 }
 
-fn markdown_to_typst(markdown: String) -> Result<Typst> {
-    // TODO: check pandoc is sufficiently advanced
-
-    let mut child = process::Command::new("pandoc")
+// Runs the external tool, passing input on stdin, returns stdout and stderr on success.
+fn run_tool(
+    tool: RenderTool,
+    command: &mut process::Command,
+    input: &[u8],
+) -> Result<(Vec<u8>, String)> {
+    let mut child = command
         .stdin(process::Stdio::piped())
         .stdout(process::Stdio::piped())
         .stderr(process::Stdio::piped())
-        .args(vec!["--from", "markdown", "--to", "typst"])
-        .spawn()?;
+        .spawn()
+        .map_err(|e| render_error(tool, format!("could not run: {}", e)))?;
     {
         let mut stdin =
             child.stdin.take().expect("Stdin via pipe requested but ChildStdin is not present");
-        stdin.write_all(markdown.as_bytes()).with_context(|| "could not pass markdown on stdin")?;
+        stdin
+            .write_all(input)
+            .map_err(|e| render_error(tool, format!("could not pass input on stdin: {}", e)))?;
     }
 
     let output = child.wait_with_output()?;
+    let stderr = String::from_utf8(output.stderr)
+        .map_err(|e| render_error(tool, format!("processing stderr failed: {}", e)))?;
+    if !output.status.success() {
+        return Err(render_error(tool, stderr));
+    }
+    Ok((output.stdout, stderr))
+}
+
+// Pandoc is only needed for LaTeX math the built-in translator does not support.
+fn markdown_to_typst(markdown: String) -> Result<Typst> {
+    match typst_markup::markdown_to_typst(&markdown) {
+        Ok(typst) => Ok(Typst(typst)),
+        Err(translator_error) => pandoc_markdown_to_typst(markdown).map_err(|_| translator_error),
+    }
+}
 
-    let status = output.status;
-    let stdout = String::from_utf8(output.stdout).with_context(|| "processing stdout failed")?;
-    let stderr = String::from_utf8(output.stderr).with_context(|| "processing stderr failed")?;
+fn pandoc_markdown_to_typst(markdown: String) -> Result<Typst> {
+    // TODO: check pandoc is sufficiently advanced
 
-    if status.success() && !stderr.is_empty() {
-        return Err(anyhow!("the output is invalid, got warnings:\n{}", stderr));
-    } else if !status.success() {
-        return Err(anyhow!("{}", stderr));
+    let (stdout, stderr) = run_tool(
+        RenderTool::Pandoc,
+        process::Command::new("pandoc").args(vec!["--from", "markdown", "--to", "typst"]),
+        markdown.as_bytes(),
+    )?;
+    let stdout = String::from_utf8(stdout).map_err(|e| {
+        render_error(RenderTool::Pandoc, format!("processing stdout failed: {}", e))
+    })?;
+
+    if !stderr.is_empty() {
+        return Err(render_error(
+            RenderTool::Pandoc,
+            format!("the output is invalid, got warnings:\n{}", stderr),
+        ));
     }
 
     Ok(Typst(stdout))
@@ -211,36 +475,120 @@ fn markdown_to_typst(markdown: String) -> Result<Typst> {
 const FONT_HEIGHT_TO_WIDTH_SCALING: f32 = 0.625;
 // Fun fact: "desktop publishing point" is exactly 1/72 of an inch.
 const POINTS_PER_INCH: f32 = 72.0;
+// The pixel density at which the card is not scaled up.
+const BASE_PPI: f32 = 96.0;
+// How much a card that does not fit is shrunk at a time.
+const ZOOM_STEP: f32 = 0.1;
+
+struct Palette {
+    foreground: &'static str,
+    background: &'static str,
+    accent: &'static str,
+    code_background: &'static str,
+    quote_bar: &'static str,
+}
+
+const LIGHT: Palette = Palette {
+    foreground: "#1f2328",
+    background: "#ffffff",
+    accent: "#0969da",
+    code_background: "#eff1f3",
+    quote_bar: "#d0d7de",
+};
+
+const DARK: Palette = Palette {
+    foreground: "#e6edf3",
+    background: "#0d1117",
+    accent: "#4493f8",
+    code_background: "#262c36",
+    quote_bar: "#3d444d",
+};
+
+// Checks the color is `#rgb`, `#rrggbb` or `#rrggbbaa` before it makes its way into Typst.
+fn typst_color(name: &str, color: &str) -> Result<String> {
+    let digits = color.strip_prefix('#').unwrap_or_default();
+    if ![3, 6, 8].contains(&digits.len()) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::Config(config::ConfigError::Message(format!(
+            "output.colors.{} should be a hex color like \"#1e1e2e\", got {:?}",
+            name, color
+        ))));
+    }
+    Ok(format!("rgb(\"{}\")", color))
+}
+
+// Without an answer from the terminal, the `auto` theme is light.
+fn theme_setup(output_settings: &OutputSettings, font_size_pt: f32) -> Result<String> {
+    let palette = match output_settings.theme {
+        Theme::Auto | Theme::Light => &LIGHT,
+        Theme::Dark => &DARK,
+    };
+    let colors = &output_settings.colors;
+    let color = |name, color: &Option<String>, default| {
+        typst_color(name, color.as_deref().unwrap_or(default))
+    };
+    let foreground = color("foreground", &colors.foreground, palette.foreground)?;
+    let background = color("background", &colors.background, palette.background)?;
+    let accent = color("accent", &colors.accent, palette.accent)?;
+    let code_background =
+        color("code_background", &colors.code_background, palette.code_background)?;
+    let quote_bar = color("quote_bar", &colors.quote_bar, palette.quote_bar)?;
+
+    let mut rv = String::new();
+    rv.push_str(&format!("#set page(fill: {})\n", background));
+    rv.push_str(&format!("#set text(fill: {})\n", foreground));
+    rv.push_str(&format!("#show link: set text(fill: {})\n", accent));
+    rv.push_str(&format!("#show heading: set text(fill: {})\n", accent));
+    rv.push_str(&format!(
+        "#show raw.where(block: false): box.with(fill: {}, inset: (x: {}pt), outset: (y: {}pt), radius: {}pt)\n",
+        code_background,
+        font_size_pt / 6.0,
+        font_size_pt / 4.0,
+        font_size_pt / 6.0
+    ));
+    rv.push_str(&format!(
+        "#show raw.where(block: true): block.with(fill: {}, inset: {}pt, radius: {}pt)\n",
+        code_background,
+        font_size_pt / 2.0,
+        font_size_pt / 6.0
+    ));
+    rv.push_str(&format!(
+        "#show quote: it => {{ rect( inset: (left: {}pt, rest: {}pt), stroke: (left: {}pt + {}, rest: none), it.body) }}\n",
+        font_size_pt, (font_size_pt/2.0), (font_size_pt/4.0), quote_bar
+    ));
+    Ok(rv)
+}
 
+// A `zoom` below 1 lays the card out on a proportionally larger page,
+// which is then rasterized at a proportionally lower pixel density,
+// so the image keeps its size while everything on it shrinks.
 fn build_typst_frontmatter(
     output_settings: &OutputSettings,
     (columns, lines): (u16, u16),
-) -> String {
+    zoom: f32,
+) -> Result<String> {
     // Convert early to avoid sprinkling conversions later
     let base_font_size_pt = output_settings.base_font_size as f32;
     let columns = columns as f32;
-    // The output during aborted review is:
+    // During review the card shares the screen with the header and the footer, see `tui`:
     //
+    // <progress>
     // Reviewing [...]
     // <card-image>
     // How much effort [...]
     // [...] Esc to nope out
-    // Error: Immediate nope out requested
-    // <terminal-prompt>
     //
-    // For a total of 5 lines
-    let lines = (lines - 5) as f32;
-    let base_ppi = 96.0;
+    // The key hints take two lines in narrower terminals, for a total of 5 lines
+    let lines = lines.saturating_sub(5).max(1) as f32;
     let ppi = output_settings.ppi;
-    let ui_scaling = ppi / base_ppi;
+    let ui_scaling = ppi / BASE_PPI;
 
     let width_pt = columns * (base_font_size_pt * FONT_HEIGHT_TO_WIDTH_SCALING);
     let width_in = width_pt / POINTS_PER_INCH;
-    let width_scaled_in = width_in * ui_scaling;
+    let width_scaled_in = width_in * ui_scaling / zoom;
 
     let height_pt = lines * base_font_size_pt * output_settings.line_height_scaling;
     let height_in = height_pt / POINTS_PER_INCH;
-    let height_scaled_in = height_in * ui_scaling;
+    let height_scaled_in = height_in * ui_scaling / zoom;
 
     let font_size_pt = base_font_size_pt * 2.0;
     let font_size_scaled_pt = font_size_pt * ui_scaling;
@@ -252,77 +600,84 @@ fn build_typst_frontmatter(
         width_scaled_in, height_scaled_in, font_size_scaled_pt,
     ));
     rv.push_str(&format!("#set text(size: {}pt)\n", font_size_scaled_pt));
+    rv.push_str(&theme_setup(output_settings, font_size_scaled_pt)?);
+    // Images are scaled down to at most `zoom` of the page height,
+    // so that they shrink along with the text and leave room for it.
     rv.push_str(&format!(
-        "#show quote: it => {{ rect( inset: (left: {}pt, rest: {}pt), stroke: (left: {}pt + gray, rest: none), it.body) }}\n",
-        font_size_scaled_pt, (font_size_scaled_pt/2.0), (font_size_scaled_pt/4.0)
+        "#show image: it => layout(size => context {{ let max = size.height * {}; if measure(it, width: size.width).height > max {{ image(it.source, height: max, alt: it.alt) }} else {{ it }} }})\n",
+        zoom
     ));
 
-    rv
+    Ok(rv)
 }
 
-fn typst_to_png(
-    typst: Typst,
-    graph_root: &Path,
-    output_settings: &OutputSettings,
-) -> Result<Vec<u8>> {
-    // typst_file needs to be in graph_root to support root relative references to assets,
-    // like `![](assets/image_1666695381725_0.png)`
-    //
-    // typst does not support page relative references to assets,
-    // like `![](../assets/image_1666695381725_0.png)`
-    //
-    // TODO: find or file an issue
-
-    let typst_frontmatter = build_typst_frontmatter(output_settings, grab_term_size());
-    let mut typst_file = NamedTempFile::new_in(graph_root)?;
-    typst_file.write_all(typst_frontmatter.as_bytes())?;
-    typst_file.write_all(typst.to_string().as_bytes())?;
-
-    let mut png_file = NamedTempFile::new()?;
-    let output = process::Command::new("typst")
-        .arg("compile")
-        .arg(format!("--ppi={}", output_settings.ppi))
-        .arg("--format=png")
-        .arg(typst_file.path())
-        .arg(png_file.path())
-        .stderr(process::Stdio::piped())
-        .output()?;
-
-    let status = output.status;
-    let stderr = String::from_utf8(output.stderr).with_context(|| "processing stderr failed")?;
-
-    if !status.success() {
-        return Err(anyhow!("{}", stderr));
-    }
-
-    let mut png_buf: Vec<u8> = Vec::new();
-    png_file.read_to_end(&mut png_buf)?;
+// Picks the line height and pixel density that make the card's lines
+// as tall as the terminal's cells of `(width, height)` pixels, as laid out by build_typst_frontmatter.
+fn fit_to_cell_size(output_settings: &mut OutputSettings, (width, height): (u16, u16)) {
+    let (width, height) = (f32::from(width), f32::from(height));
+    output_settings.line_height_scaling = FONT_HEIGHT_TO_WIDTH_SCALING * height / width;
+    // A line is `base_font_size * line_height_scaling * ppi / BASE_PPI` points tall,
+    // rasterized at `ppi / POINTS_PER_INCH` pixels per point.
+    let line_height_pt =
+        output_settings.base_font_size as f32 * output_settings.line_height_scaling;
+    output_settings.ppi = (height * POINTS_PER_INCH * BASE_PPI / line_height_pt).sqrt();
+}
 
-    Ok(png_buf)
+// Backgrounds darker than mid gray, by perceived brightness, are dark.
+fn is_dark((red, green, blue): (u8, u8, u8)) -> bool {
+    0.299 * f32::from(red) + 0.587 * f32::from(green) + 0.114 * f32::from(blue) < 127.5
 }
 
-fn png_to_sixel(png_buf: Vec<u8>) -> Result<Vec<u8>> {
-    // TODO: use libsixel instead of shelling out
-    let mut child = process::Command::new("img2sixel")
-        .stdin(process::Stdio::piped())
-        .stdout(process::Stdio::piped())
-        .stderr(process::Stdio::piped())
-        .spawn()?;
-    {
-        let mut stdin =
-            child.stdin.take().expect("Stdin via pipe requested but ChildStdin is not present");
-        stdin.write_all(&png_buf).with_context(|| "could not pass png on stdin")?;
+// Replaces the `auto` format with the best one the terminal supports,
+// inferring `ppi` and `line_height_scaling` from the terminal's cell size when it is reported,
+// and the `auto` theme of image based formats with the one matching the terminal's background.
+pub fn resolve_auto_settings(output_settings: &mut OutputSettings) {
+    let auto_format = matches!(output_settings.format, OutputFormat::Auto);
+    let auto_theme = matches!(output_settings.theme, Theme::Auto)
+        && (auto_format || output_settings.format.is_image());
+    if !auto_format && !auto_theme {
+        return;
     }
+    let probe = probe_terminal();
+    if auto_format {
+        output_settings.format = probe.format;
+        if let Some(cell_size) = probe.cell_size {
+            fit_to_cell_size(output_settings, cell_size);
+        }
+    }
+    if auto_theme && let Some(background) = probe.background {
+        output_settings.theme = if is_dark(background) { Theme::Dark } else { Theme::Light };
+    }
+}
 
-    let output = child.wait_with_output()?;
-    let status = output.status;
-    let stderr = String::from_utf8(output.stderr).with_context(|| "processing stderr failed")?;
-
-    if !status.success() {
-        return Err(anyhow!("{}", stderr));
+// Cards that do not fit in the terminal are shrunk step by step, down to `min_font_scaling`,
+// those that still do not fit are split into pages.
+fn typst_to_pngs(
+    typst: Typst,
+    graph_root: &Path,
+    output_settings: &OutputSettings,
+    term_size: (u16, u16),
+) -> Result<Vec<Vec<u8>>> {
+    // The card is compiled in memory as a file in graph_root,
+    // see `rewrite_image_urls` for how references to assets are resolved.
+    let min_zoom = output_settings.min_font_scaling.clamp(0.1, 1.0);
+    let mut step = 0;
+    loop {
+        let zoom = (1.0 - step as f32 * ZOOM_STEP).max(min_zoom);
+        let mut source = build_typst_frontmatter(output_settings, term_size, zoom)?;
+        source.push_str(&typst.0);
+        let document = CardWorld::new(graph_root, source)?.compile()?;
+        if document.pages().len() <= 1 || zoom <= min_zoom {
+            return document_to_pngs(&document, output_settings.ppi * zoom);
+        }
+        step += 1;
     }
+}
 
-    Ok(output.stdout)
+fn png_to_sixel(png_buf: &[u8]) -> Result<Vec<u8>> {
+    let img = image::load_from_memory_with_format(png_buf, image::ImageFormat::Png)
+        .map_err(|e| render_error(RenderTool::Image, e))?;
+    Ok(encode_sixel(&img.to_rgba8()))
 }
 
 fn format_card_logseq_text(
@@ -362,6 +717,9 @@ fn format_card_logseq_srs_meta(
         "{indent}card-fsrs-metadata:: {}",
         serde_json::to_string(&srs_meta.fsrs_meta)?
     )?;
+    if srs_meta.suspended {
+        writeln!(writer, "{indent}card-suspended:: true")?;
+    }
 
     Ok(())
 }
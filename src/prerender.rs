@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+
+use losrs::output::warm_image_cache;
+use losrs::settings::OutputSettings;
+use losrs::types::Card;
+
+// Renders images of upcoming cards into the image cache on worker threads,
+// so that each card is shown right away when its turn comes.
+pub struct Prerenderer {
+    sender: Option<mpsc::Sender<(Card, (u16, u16))>>,
+    stopping: Arc<AtomicBool>,
+}
+
+impl Prerenderer {
+    pub fn new(output_settings: &OutputSettings, workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<(Card, (u16, u16))>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let stopping = Arc::new(AtomicBool::new(false));
+        for _ in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let stopping = Arc::clone(&stopping);
+            let output_settings = output_settings.clone();
+            thread::spawn(move || {
+                loop {
+                    // The lock is held while waiting for a card, not while rendering it.
+                    let received = receiver.lock().unwrap().recv();
+                    let Ok((card, term_size)) = received else { break };
+                    if stopping.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // Failures show up again when the card itself is shown.
+                    let _ = warm_image_cache(&card, &output_settings, term_size);
+                }
+            });
+        }
+        Self { sender: Some(sender), stopping }
+    }
+
+    // Images are laid out for `term_size`, like `render_card_for_terminal` does.
+    pub fn submit(&self, card: Card, term_size: (u16, u16)) {
+        if let Some(sender) = &self.sender {
+            // Workers only go away when the prerenderer is dropped.
+            let _ = sender.send((card, term_size));
+        }
+    }
+}
+
+// Cards still waiting are dropped. Cards being rendered are not waited for,
+// a render cut short at exit leaves nothing behind, as the cache stores images whole.
+impl Drop for Prerenderer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        self.sender.take();
+    }
+}
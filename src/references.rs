@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Captures;
+use regex::Regex;
+
+use crate::normalize::is_property;
+
+static BLOCK_ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^id:: *(?<uuid>[0-9a-fA-F-]{36}) *$").unwrap());
+// Embeds come first, so that their block reference is not matched on its own.
+static REFERENCE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"\{\{embed \(\((?<embed_uuid>[0-9a-fA-F-]{36})\)\)\}\}",
+        r"|\{\{embed \[\[(?<embed_page>[^\[\]]+)\]\]\}\}",
+        r"|\(\((?<uuid>[0-9a-fA-F-]{36})\)\)",
+    ))
+    .unwrap()
+});
+
+// Tabs are how Logseq indents blocks, spaces are counted the same as in markdown.
+fn indent_width(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).map(|c| if c == '\t' { 2 } else { 1 }).sum()
+}
+
+fn bullet_text(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line == "-" { Some("") } else { line.strip_prefix("- ") }
+}
+
+// Removes the indentation of the first line from all of them.
+fn unindent(lines: &[&str]) -> String {
+    let Some(first) = lines.first() else { return String::new() };
+    let prefix = &first[..first.len() - first.trim_start().len()];
+    lines
+        .iter()
+        .filter(|l| !is_property(l))
+        .map(|l| l.strip_prefix(prefix).unwrap_or(l.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct Block {
+    // The text of the block itself, without properties.
+    content: String,
+    // Nested blocks as a markdown list.
+    children: String,
+}
+
+// Logseq stores namespaced pages like `a/b` as `a___b.md`, unless the page has a `title::`.
+fn page_name(path: &Path, page_raw: &str) -> String {
+    let title = page_raw
+        .lines()
+        .take_while(|l| is_property(l))
+        .find_map(|l| l.trim().strip_prefix("title::"))
+        .map(str::trim)
+        .filter(|title| !title.is_empty());
+    match title {
+        Some(title) => title.to_owned(),
+        None => path.file_stem().unwrap_or_default().to_string_lossy().replace("___", "/"),
+    }
+}
+
+// Built the first time a card is displayed, so that other commands never read the whole graph.
+pub type LazyGraphIndex = LazyLock<GraphIndex, Box<dyn FnOnce() -> GraphIndex + Send>>;
+
+// Blocks with an `id::` property by their UUID and page contents by their lowercase names,
+// for resolving `((uuid))` references and `{{embed ...}}` of cards.
+#[derive(Default)]
+pub struct GraphIndex {
+    blocks: HashMap<String, Block>,
+    pages: HashMap<String, String>,
+}
+
+impl GraphIndex {
+    // Files that can not be read, or are not UTF-8, are left out.
+    pub fn build(page_files: &[impl AsRef<Path>]) -> GraphIndex {
+        let mut index = GraphIndex::default();
+        for page_file in page_files {
+            let page_file = page_file.as_ref();
+            let Ok(page_raw) = fs::read_to_string(page_file) else { continue };
+            index.add_page(page_file, &page_raw);
+        }
+        index
+    }
+
+    fn add_page(&mut self, path: &Path, page_raw: &str) {
+        let lines: Vec<&str> = page_raw.lines().collect();
+        let content_start = lines.iter().position(|l| !is_property(l)).unwrap_or(lines.len());
+        self.pages
+            .insert(page_name(path, page_raw).to_lowercase(), unindent(&lines[content_start..]));
+
+        for (start, line) in lines.iter().enumerate() {
+            let Some(text) = bullet_text(line) else { continue };
+            let indent = indent_width(line);
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| bullet_text(l).is_some() && indent_width(l) <= indent)
+                .map_or(lines.len(), |offset| start + 1 + offset);
+            let children_start = lines[start + 1..end]
+                .iter()
+                .position(|l| bullet_text(l).is_some())
+                .map_or(end, |offset| start + 1 + offset);
+            let own_lines = &lines[start + 1..children_start];
+            let Some(uuid) = own_lines
+                .iter()
+                .find_map(|l| BLOCK_ID_RE.captures(l.trim()))
+                .map(|caps| caps["uuid"].to_lowercase())
+            else {
+                continue;
+            };
+            let mut content = vec![text];
+            content.extend(own_lines.iter().map(|l| l.trim()).filter(|l| !is_property(l)));
+            let children = unindent(&lines[children_start..end]);
+            self.blocks.insert(uuid, Block { content: content.join("\n"), children });
+        }
+    }
+
+    // Substitutes block references with the text of the block and embeds with the block
+    // and its children, or with the whole page, up to `max_depth` levels deep.
+    // References that are too deep, form a cycle or point nowhere are left as they are.
+    pub fn resolve(&self, markdown: &str, max_depth: u32) -> String {
+        self.resolve_inner(markdown, max_depth, &mut Vec::new())
+    }
+
+    fn resolve_inner(&self, markdown: &str, depth: u32, expanding: &mut Vec<String>) -> String {
+        markdown
+            .lines()
+            .map(|line| {
+                // Multi-line substitutions continue at the start of the line's text.
+                let mut continuation = line[..line.len() - line.trim_start().len()].to_owned();
+                if bullet_text(line).is_some() {
+                    continuation.push_str("  ");
+                }
+                REFERENCE_RE
+                    .replace_all(line, |caps: &Captures| {
+                        self.substitute(caps, depth, expanding)
+                            .unwrap_or_else(|| caps[0].to_owned())
+                            .replace('\n', &format!("\n{}", continuation))
+                    })
+                    .into_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn substitute(
+        &self,
+        caps: &Captures,
+        depth: u32,
+        expanding: &mut Vec<String>,
+    ) -> Option<String> {
+        let (key, text) = if let Some(uuid) = caps.name("uuid") {
+            let uuid = uuid.as_str().to_lowercase();
+            let text = self.blocks.get(&uuid)?.content.clone();
+            (uuid, text)
+        } else if let Some(uuid) = caps.name("embed_uuid") {
+            let uuid = uuid.as_str().to_lowercase();
+            let block = self.blocks.get(&uuid)?;
+            let text = match block.children.is_empty() {
+                true => block.content.clone(),
+                false => format!("{}\n{}", block.content, block.children),
+            };
+            (uuid, text)
+        } else {
+            let name = caps["embed_page"].to_lowercase();
+            let text = self.pages.get(&name)?.clone();
+            (name, text)
+        };
+        if depth == 0 || expanding.contains(&key) {
+            return None;
+        }
+        expanding.push(key);
+        let resolved = self.resolve_inner(&text, depth - 1, expanding);
+        expanding.pop();
+        Some(resolved)
+    }
+}
@@ -1,27 +1,37 @@
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
-use anyhow::Context;
-use anyhow::Ok;
-use anyhow::Result;
-use anyhow::anyhow;
 use chrono::DateTime;
 use chrono::FixedOffset;
+use chrono::NaiveTime;
+use chrono::TimeDelta;
 use chrono::Utc;
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
 use rs_fsrs::FSRS;
 use rs_fsrs::Rating;
 
-use crate::output::show_card;
-use crate::output::show_card_prompt;
+use crate::error::Error;
+use crate::error::Result;
+use crate::output::CardBodyParts;
+use crate::output::render_card_for_terminal;
+use crate::references::LazyGraphIndex;
 use crate::settings::OutputSettings;
+use crate::settings::ReviewSettings;
 use crate::storage::StorageManager;
 use crate::terminal::PreReviewResponse;
 use crate::terminal::ReviewResponse;
-use crate::terminal::clear_screen;
-use crate::terminal::wait_for_prereview;
-use crate::terminal::wait_for_review;
+use crate::tui::ReviewScreen;
 use crate::types::Card;
+use crate::types::CardId;
 use crate::types::CardMetadata;
+use crate::types::CardRef;
+use crate::types::CardReviewLog;
 use crate::types::FSRSMeta;
+use crate::types::ReviewLog;
 use crate::types::SRSMeta;
 
 impl From<&ReviewResponse> for Rating {
@@ -43,11 +53,10 @@ struct ReviewableFSRSMeta<'a> {
 impl<'a> ReviewableFSRSMeta<'a> {
     fn new(fsrs_meta: &'a FSRSMeta, reviewed_at: DateTime<FixedOffset>) -> Result<Self> {
         if reviewed_at < fsrs_meta.last_review.fixed_offset() {
-            return Err(anyhow!(
-                "reviewing a card that was last reviewed on {:?} at {:?}, before it was last reviewed!",
-                fsrs_meta.last_review,
-                reviewed_at
-            ));
+            return Err(Error::ReviewBeforeLastReview {
+                reviewed_at,
+                last_review: fsrs_meta.last_review,
+            });
         };
         Ok(Self { inner: fsrs_meta, reviewed_at })
     }
@@ -71,20 +80,59 @@ fn clean_up_fsrs_meta(value: &FSRSMeta) -> FSRSMeta {
     }
 }
 
-fn compute_next_fsrs_meta(fsrs_meta: &ReviewableFSRSMeta, resp: &ReviewResponse) -> FSRSMeta {
-    let reviewed_at = fsrs_meta.reviewed_at;
+fn clean_up_review_log(value: &ReviewLog) -> ReviewLog {
+    ReviewLog { reviewed_date: truncate_to_millis(&value.reviewed_date), ..value.clone() }
+}
+
+fn fsrs_scheduler() -> FSRS {
     let fsrs_params = rs_fsrs::Parameters { enable_short_term: false, ..Default::default() };
-    let fsrs = FSRS::new(fsrs_params);
+    FSRS::new(fsrs_params)
+}
 
-    let next = fsrs.next(fsrs_meta.inner.clone(), reviewed_at.into(), resp.into());
-    clean_up_fsrs_meta(&next.card)
+fn compute_next_fsrs_meta(fsrs_meta: &ReviewableFSRSMeta, rating: Rating) -> (FSRSMeta, ReviewLog) {
+    let reviewed_at = fsrs_meta.reviewed_at;
+    let next = fsrs_scheduler().next(fsrs_meta.inner.clone(), reviewed_at.into(), rating);
+    (clean_up_fsrs_meta(&next.card), clean_up_review_log(&next.review_log))
+}
+
+// Replays past reviews, oldest first, starting from a new card.
+// Used to carry over review history from other tools.
+pub fn replay_reviews(reviews: &[(DateTime<Utc>, Rating)]) -> (FSRSMeta, Vec<ReviewLog>) {
+    let fsrs = fsrs_scheduler();
+    let mut fsrs_meta = FSRSMeta::default();
+    let mut review_logs = Vec::new();
+    for (reviewed_at, rating) in reviews {
+        let next = fsrs.next(fsrs_meta, *reviewed_at, *rating);
+        fsrs_meta = clean_up_fsrs_meta(&next.card);
+        review_logs.push(clean_up_review_log(&next.review_log));
+    }
+    (fsrs_meta, review_logs)
 }
 
-fn compute_next_srs_meta(fsrs_meta: &ReviewableFSRSMeta, resp: &ReviewResponse) -> SRSMeta {
-    let next_fsrs_meta = compute_next_fsrs_meta(fsrs_meta, resp);
+fn compute_next_srs_meta_inner(
+    fsrs_meta: &ReviewableFSRSMeta,
+    rating: Rating,
+) -> (SRSMeta, ReviewLog) {
+    let (next_fsrs_meta, review_log) = compute_next_fsrs_meta(fsrs_meta, rating);
     let next_logseq_srs_meta = (&next_fsrs_meta).into();
 
-    SRSMeta { logseq_srs_meta: next_logseq_srs_meta, fsrs_meta: next_fsrs_meta }
+    let srs_meta = SRSMeta {
+        logseq_srs_meta: next_logseq_srs_meta,
+        fsrs_meta: next_fsrs_meta,
+        suspended: false,
+    };
+    (srs_meta, review_log)
+}
+
+// Schedules the card after it was reviewed with the given rating,
+// the card must not have been reviewed after reviewed_at.
+pub fn compute_next_srs_meta(
+    fsrs_meta: &FSRSMeta,
+    reviewed_at: DateTime<FixedOffset>,
+    rating: Rating,
+) -> Result<(SRSMeta, ReviewLog)> {
+    let reviewable_fsrs_meta = ReviewableFSRSMeta::new(fsrs_meta, reviewed_at)?;
+    Ok(compute_next_srs_meta_inner(&reviewable_fsrs_meta, rating))
 }
 
 fn compute_delayed_srs_meta(fsrs_meta: &ReviewableFSRSMeta, delay: Duration) -> SRSMeta {
@@ -94,74 +142,256 @@ fn compute_delayed_srs_meta(fsrs_meta: &ReviewableFSRSMeta, delay: Duration) ->
     delayed_fsrs_meta.due = truncate_to_millis(&(fsrs_meta.reviewed_at + delay).into());
     let delayed_logseq_srs_meta = (&delayed_fsrs_meta).into();
 
-    SRSMeta { logseq_srs_meta: delayed_logseq_srs_meta, fsrs_meta: delayed_fsrs_meta }
-}
-
-fn format_reviewing_phrase(cm: &CardMetadata) -> String {
-    match cm.card_ref.serial_num {
-        Some(serial_num) => format!(
-            "Reviewing card with serial number {} from {}",
-            serial_num,
-            cm.card_ref.source_path.display()
-        ),
-        None => format!(
-            "Reviewing card with prompt fingerprint {} from {}",
-            cm.card_ref.prompt_fingerprint,
-            cm.card_ref.source_path.display()
-        ),
+    SRSMeta {
+        logseq_srs_meta: delayed_logseq_srs_meta,
+        fsrs_meta: delayed_fsrs_meta,
+        suspended: false,
     }
 }
 
+// Schedules the card according to the rating and stores the result along with the review log.
+// Returns the card_ref as it is after the rewrite.
+pub fn rate_card(
+    cm: &CardMetadata,
+    reviewed_at: DateTime<FixedOffset>,
+    rating: Rating,
+    review_duration: Duration,
+    storage_manager: &mut StorageManager,
+) -> Result<CardRef> {
+    let (srs_meta, review_log) =
+        compute_next_srs_meta(&cm.srs_meta.fsrs_meta, reviewed_at, rating)?;
+    let srs_meta = SRSMeta { suspended: cm.srs_meta.suspended, ..srs_meta };
+    let card_ref = storage_manager.rewrite_card_meta(&cm.card_ref, &srs_meta)?;
+    storage_manager.append_review_log(&card_ref, &review_log, review_duration)?;
+    Ok(card_ref)
+}
+
+// Postpones the card without reviewing it, nothing is logged.
+// Returns the card_ref as it is after the rewrite.
+pub fn delay_card(
+    cm: &CardMetadata,
+    reviewed_at: DateTime<FixedOffset>,
+    delay: Duration,
+    storage_manager: &mut StorageManager,
+) -> Result<CardRef> {
+    let reviewable_fsrs_meta = ReviewableFSRSMeta::new(&cm.srs_meta.fsrs_meta, reviewed_at)?;
+    let srs_meta = SRSMeta {
+        suspended: cm.srs_meta.suspended,
+        ..compute_delayed_srs_meta(&reviewable_fsrs_meta, delay)
+    };
+    storage_manager.rewrite_card_meta(&cm.card_ref, &srs_meta)
+}
+
+// Everything needed to undo an answer given during review.
+pub struct AnsweredReview {
+    // card_ref after the answer was written, the serial number might have been allocated.
+    card_ref: CardRef,
+    previous_srs_meta: SRSMeta,
+    logged: bool,
+    graph_index: Option<Arc<LazyGraphIndex>>,
+}
+
+pub enum ReviewOutcome {
+    Answered(AnsweredReview),
+    UndoRequested,
+}
+
+// How long until the card is due again for each answer, in the order of their keys.
+fn predict_intervals(fsrs_meta: &ReviewableFSRSMeta) -> Vec<TimeDelta> {
+    ReviewResponse::ALL
+        .iter()
+        .map(|review_response| {
+            let (next_fsrs_meta, _) = compute_next_fsrs_meta(fsrs_meta, review_response.into());
+            next_fsrs_meta.due - fsrs_meta.reviewed_at.to_utc()
+        })
+        .collect()
+}
+
 // TODO: supply only card_ref and fsrs_meta
 pub fn review_card(
     cm: &CardMetadata,
     reviewed_at: DateTime<FixedOffset>,
     output_settings: &OutputSettings,
     storage_manager: &mut StorageManager,
-) -> Result<()> {
+    screen: &mut ReviewScreen,
+) -> Result<ReviewOutcome> {
     // We construct ReviewableFSRSMeta early so as to not require user action
     // if card is unreviewable.
     let reviewable_fsrs_meta = ReviewableFSRSMeta::new(&cm.srs_meta.fsrs_meta, reviewed_at)?;
 
-    let card_body = storage_manager.load_card_body_by_ref(&cm.card_ref).with_context(|| {
-        format!(
-            "When extracting card with fingerprint {} from {}",
-            cm.card_ref.prompt_fingerprint,
-            cm.card_ref.source_path.display(),
-        )
-    })?;
+    let card_body = storage_manager.load_card_body_by_ref(&cm.card_ref)?;
     let card = Card { metadata: cm.clone(), body: card_body };
 
-    clear_screen()?;
-    let review_phrase = format_reviewing_phrase(cm);
-    println!("{}", review_phrase);
+    // The screen renders the card again when the room for it changes.
+    let render_prompt = |term_size| {
+        render_card_for_terminal(&card, CardBodyParts::PROMPT, output_settings, term_size)
+    };
+    let render_whole =
+        |term_size| render_card_for_terminal(&card, CardBodyParts::ALL, output_settings, term_size);
 
-    // TODO: make show_card returns bytes,
-    // so that we can print everything together, without delay.
-    // 1. Show progressbar
-    // 2. Format card into buffer
-    // 3. Complete progressbar
-    // 4. Show the whole thing
-    show_card_prompt(&card, output_settings)?;
+    // TODO: show a progressbar while the card is rendered
+    let prompt = render_prompt(screen.card_size())?;
+    let review_start = Instant::now();
 
-    let prereview_response = wait_for_prereview()?;
+    let prereview_response = screen.wait_for_prereview(cm, prompt, &render_prompt)?;
 
-    let new_srs_meta = match prereview_response {
+    let (card_ref, logged) = match prereview_response {
         PreReviewResponse::ShowResponse => {
-            clear_screen()?;
-            println!("{}", review_phrase);
+            let whole = render_whole(screen.card_size())?;
 
-            show_card(&card, output_settings)?;
-
-            let review_response = wait_for_review()?;
-            compute_next_srs_meta(&reviewable_fsrs_meta, &review_response)
+            let intervals = predict_intervals(&reviewable_fsrs_meta);
+            let review_response = screen.wait_for_review(cm, whole, &render_whole, &intervals)?;
+            let rating = (&review_response).into();
+            let review_duration = review_start.elapsed();
+            (rate_card(cm, reviewed_at, rating, review_duration, storage_manager)?, true)
         }
         PreReviewResponse::DelayReview => {
-            compute_delayed_srs_meta(&reviewable_fsrs_meta, Duration::from_hours(24))
+            (delay_card(cm, reviewed_at, Duration::from_hours(24), storage_manager)?, false)
         }
+        PreReviewResponse::UndoPrevious => return Ok(ReviewOutcome::UndoRequested),
     };
 
-    storage_manager.rewrite_card_meta(&card.metadata.card_ref, &new_srs_meta)?;
+    Ok(ReviewOutcome::Answered(AnsweredReview {
+        card_ref,
+        previous_srs_meta: card.metadata.srs_meta,
+        logged,
+        graph_index: card.metadata.graph_index,
+    }))
+}
+
+// Restores card metadata to what it was before the answer,
+// returns the card metadata to review the card again.
+pub fn undo_review(
+    answered_review: AnsweredReview,
+    storage_manager: &mut StorageManager,
+) -> Result<CardMetadata> {
+    let card_ref = storage_manager
+        .rewrite_card_meta(&answered_review.card_ref, &answered_review.previous_srs_meta)?;
+    if answered_review.logged {
+        storage_manager.remove_last_review_log(&card_ref)?;
+    }
+    Ok(CardMetadata {
+        card_ref,
+        srs_meta: answered_review.previous_srs_meta,
+        graph_index: answered_review.graph_index,
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct DailyReviewCounts {
+    pub new_cards: u32,
+    pub reviews: u32,
+}
+
+impl DailyReviewCounts {
+    fn from_review_logs(card_review_logs: &[CardReviewLog], day_start: DateTime<Utc>) -> Self {
+        let mut counts = Self::default();
+        for crl in card_review_logs.iter().filter(|crl| crl.review_log.reviewed_date >= day_start) {
+            match crl.review_log.state {
+                rs_fsrs::State::New => counts.new_cards += 1,
+                _ => counts.reviews += 1,
+            }
+        }
+        counts
+    }
+
+    // Without review history we can only tell how many cards were last reviewed today,
+    // so a card reviewed twice today is counted once.
+    fn from_card_metas(card_metas: &[CardMetadata], day_start: DateTime<Utc>) -> Self {
+        let mut counts = Self::default();
+        for fsrs_meta in card_metas.iter().map(|cm| &cm.srs_meta.fsrs_meta) {
+            if fsrs_meta.state == rs_fsrs::State::New || fsrs_meta.last_review < day_start {
+                continue;
+            }
+            if fsrs_meta.reps == 1 { counts.new_cards += 1 } else { counts.reviews += 1 }
+        }
+        counts
+    }
+}
+
+pub fn count_reviewed_today(
+    card_metas: &[CardMetadata],
+    storage_manager: &StorageManager,
+    reviewed_at: DateTime<FixedOffset>,
+) -> Result<DailyReviewCounts> {
+    // "Today" is in the timezone of the review time, a fixed offset has no gaps or folds.
+    let day_start = reviewed_at
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(*reviewed_at.offset())
+        .single()
+        .expect("the start of the day is unambiguous with a fixed offset")
+        .to_utc();
+    match storage_manager.load_review_logs()? {
+        Some(card_review_logs) => {
+            Ok(DailyReviewCounts::from_review_logs(&card_review_logs, day_start))
+        }
+        None => Ok(DailyReviewCounts::from_card_metas(card_metas, day_start)),
+    }
+}
+
+// Returns cards that fit into the daily limits, preserving their order,
+// and the number of cards that did not fit.
+pub fn apply_daily_limits(
+    card_metas: Vec<CardMetadata>,
+    review_settings: &ReviewSettings,
+    reviewed_today: &DailyReviewCounts,
+) -> (Vec<CardMetadata>, usize) {
+    let mut new_cards_left =
+        review_settings.new_cards_per_day.saturating_sub(reviewed_today.new_cards);
+    let mut reviews_left =
+        review_settings.max_reviews_per_day.saturating_sub(reviewed_today.reviews);
 
-    Ok(())
+    let mut held_back = 0;
+    let mut selected = Vec::new();
+    for cm in card_metas {
+        let left = match cm.srs_meta.fsrs_meta.state {
+            rs_fsrs::State::New => &mut new_cards_left,
+            _ => &mut reviews_left,
+        };
+        if *left == 0 {
+            held_back += 1;
+            continue;
+        }
+        *left -= 1;
+        selected.push(cm);
+    }
+    (selected, held_back)
+}
+
+// Narrows down and orders the cards `select_due_cards` picks.
+#[derive(Default)]
+pub struct DueCardsFilter {
+    // An explicitly requested card is picked even when suspended or over the daily limits.
+    pub card_id: Option<CardId>,
+    // Picks cards due up to this time instead of those due at the time of the review.
+    pub up_to: Option<DateTime<FixedOffset>>,
+    // Shuffles the cards with this seed instead of putting the oldest due first.
+    pub shuffle_seed: Option<u64>,
+}
+
+// Cards due at the given time that fit into the daily limits, oldest due first,
+// and the number of due cards that did not fit. Suspended cards are skipped.
+// Every frontend picks cards here, so that they review the same cards in the same order.
+pub fn select_due_cards(
+    path: &Path,
+    storage_manager: &StorageManager,
+    review_settings: &ReviewSettings,
+    at: DateTime<FixedOffset>,
+    filter: DueCardsFilter,
+) -> Result<(Vec<CardMetadata>, usize)> {
+    let requested = filter.card_id.is_some();
+    let mut card_metas = storage_manager.select_card_metadata(path, filter.card_id)?;
+    let reviewed_today = count_reviewed_today(&card_metas, storage_manager, at)?;
+    let up_to = filter.up_to.unwrap_or(at);
+    card_metas
+        .retain(|cm| (requested || !cm.srs_meta.suspended) && cm.srs_meta.fsrs_meta.due <= up_to);
+    match filter.shuffle_seed {
+        Some(seed) => card_metas.shuffle(&mut SmallRng::seed_from_u64(seed)),
+        None => card_metas.sort_by_key(|cm| cm.srs_meta.fsrs_meta.due),
+    }
+    if requested {
+        return Ok((card_metas, 0));
+    }
+    Ok(apply_daily_limits(card_metas, review_settings, &reviewed_today))
 }
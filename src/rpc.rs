@@ -0,0 +1,316 @@
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::DateTime;
+use chrono::FixedOffset;
+use rs_fsrs::Rating;
+use serde::Deserialize;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_json::json;
+use serde_json::value::RawValue;
+
+use losrs::review;
+use losrs::review::DueCardsFilter;
+use losrs::settings::ReviewSettings;
+use losrs::stats::StatsReport;
+use losrs::storage::StorageManager;
+use losrs::types::CardMetadata;
+use losrs::types::CardRef;
+use losrs::types::FSRSMeta;
+use losrs::types::SRSMeta;
+
+// Error codes defined by JSON-RPC 2.0
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Failures while handling a well formed request, like a card missing from its page.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    // Notifications do not have an id and do not get a response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+// Results are kept serialized, so that their fields stay in declaration order.
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Box<RawValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self { code, message: message.to_string() }
+    }
+}
+
+fn now() -> DateTime<FixedOffset> {
+    chrono::offset::Local::now().fixed_offset()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RpcRating {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+impl From<RpcRating> for Rating {
+    fn from(value: RpcRating) -> Self {
+        match value {
+            RpcRating::Again => Rating::Again,
+            RpcRating::Hard => Rating::Hard,
+            RpcRating::Good => Rating::Good,
+            RpcRating::Easy => Rating::Easy,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DueCardsParams {
+    at: Option<DateTime<FixedOffset>>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CardParams {
+    card_ref: CardRef,
+}
+
+#[derive(Deserialize)]
+struct RateParams {
+    card_ref: CardRef,
+    rating: RpcRating,
+    at: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    duration_ms: u64,
+}
+
+#[derive(Deserialize)]
+struct DelayParams {
+    card_ref: CardRef,
+    at: Option<DateTime<FixedOffset>>,
+    #[serde(default = "DelayParams::default_hours")]
+    hours: f64,
+}
+
+impl DelayParams {
+    fn default_hours() -> f64 {
+        24.0
+    }
+}
+
+#[derive(Deserialize)]
+struct SuspendParams {
+    card_ref: CardRef,
+    #[serde(default = "SuspendParams::default_suspended")]
+    suspended: bool,
+}
+
+impl SuspendParams {
+    fn default_suspended() -> bool {
+        true
+    }
+}
+
+#[derive(Deserialize)]
+struct StatsParams {
+    at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Serialize)]
+struct CardInfo {
+    card_ref: CardRef,
+    fsrs_meta: FSRSMeta,
+    suspended: bool,
+}
+
+impl From<CardMetadata> for CardInfo {
+    fn from(cm: CardMetadata) -> Self {
+        Self {
+            card_ref: cm.card_ref,
+            fsrs_meta: cm.srs_meta.fsrs_meta,
+            suspended: cm.srs_meta.suspended,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DueCards {
+    cards: Vec<CardInfo>,
+    held_back: usize,
+}
+
+#[derive(Serialize)]
+struct CardWithBody {
+    #[serde(flatten)]
+    info: CardInfo,
+    prompt: String,
+    response: String,
+}
+
+struct Server<'a> {
+    path: &'a Path,
+    storage_manager: &'a mut StorageManager,
+    review_settings: &'a ReviewSettings,
+}
+
+// Params are deserialized before calling the handler, so that malformed ones
+// are reported as such rather than as a failure to handle the request.
+fn call<P: DeserializeOwned, R: Serialize>(
+    params: Value,
+    handler: impl FnOnce(P) -> Result<R>,
+) -> Result<Box<RawValue>, RpcError> {
+    // Methods without required params can be called without any.
+    let params = if params.is_null() { json!({}) } else { params };
+    let params = serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+    let result = handler(params).map_err(|e| RpcError::new(SERVER_ERROR, format!("{:#}", e)))?;
+    serde_json::value::to_raw_value(&result).map_err(|e| RpcError::new(SERVER_ERROR, e))
+}
+
+impl Server<'_> {
+    fn find_card_meta(&self, card_ref: &CardRef) -> Result<CardMetadata> {
+        Ok(self.storage_manager.load_card_meta_by_ref(card_ref)?)
+    }
+
+    fn due_cards(&mut self, params: DueCardsParams) -> Result<DueCards> {
+        let at = params.at.unwrap_or_else(now);
+        let (mut card_metas, mut held_back) = review::select_due_cards(
+            self.path,
+            self.storage_manager,
+            self.review_settings,
+            at,
+            DueCardsFilter::default(),
+        )?;
+        if let Some(limit) = params.limit.filter(|limit| *limit < card_metas.len()) {
+            held_back += card_metas.len() - limit;
+            card_metas.truncate(limit);
+        }
+        Ok(DueCards { cards: card_metas.into_iter().map(CardInfo::from).collect(), held_back })
+    }
+
+    fn card(&mut self, params: CardParams) -> Result<CardWithBody> {
+        let cm = self.find_card_meta(&params.card_ref)?;
+        let body = self.storage_manager.load_card_body_by_ref(&cm.card_ref)?;
+        Ok(CardWithBody {
+            info: cm.into(),
+            prompt: body.prompt_text(),
+            response: body.response_text(),
+        })
+    }
+
+    fn rate(&mut self, params: RateParams) -> Result<CardInfo> {
+        let cm = self.find_card_meta(&params.card_ref)?;
+        let card_ref = review::rate_card(
+            &cm,
+            params.at.unwrap_or_else(now),
+            params.rating.into(),
+            Duration::from_millis(params.duration_ms),
+            self.storage_manager,
+        )?;
+        Ok(self.find_card_meta(&card_ref)?.into())
+    }
+
+    fn delay(&mut self, params: DelayParams) -> Result<CardInfo> {
+        let cm = self.find_card_meta(&params.card_ref)?;
+        let delay = Duration::try_from_secs_f64(params.hours * 3600.0)?;
+        let card_ref =
+            review::delay_card(&cm, params.at.unwrap_or_else(now), delay, self.storage_manager)?;
+        Ok(self.find_card_meta(&card_ref)?.into())
+    }
+
+    fn suspend(&mut self, params: SuspendParams) -> Result<CardInfo> {
+        let cm = self.find_card_meta(&params.card_ref)?;
+        let srs_meta = SRSMeta { suspended: params.suspended, ..cm.srs_meta };
+        let card_ref = self.storage_manager.rewrite_card_meta(&cm.card_ref, &srs_meta)?;
+        Ok(self.find_card_meta(&card_ref)?.into())
+    }
+
+    fn stats(&mut self, params: StatsParams) -> Result<StatsReport> {
+        let at = params.at.unwrap_or_else(now);
+        let card_metas = self.storage_manager.select_card_metadata(self.path, None)?;
+        let card_review_logs = self.storage_manager.load_review_logs()?;
+        Ok(StatsReport::new(&card_metas, card_review_logs.as_deref(), at))
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Box<RawValue>, RpcError> {
+        match method {
+            "due_cards" => call(params, |p| self.due_cards(p)),
+            "card" => call(params, |p| self.card(p)),
+            "rate" => call(params, |p| self.rate(p)),
+            "delay" => call(params, |p| self.delay(p)),
+            "suspend" => call(params, |p| self.suspend(p)),
+            "stats" => call(params, |p| self.stats(p)),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        }
+    }
+
+    // Returns None for notifications.
+    fn handle_message(&mut self, message: &str) -> Option<Response> {
+        let (id, result) = match serde_json::from_str::<Value>(message) {
+            Err(e) => (Value::Null, Err(RpcError::new(PARSE_ERROR, e))),
+            Ok(value) => {
+                let id = value.get("id").cloned().unwrap_or_default();
+                match serde_json::from_value::<Request>(value) {
+                    Err(e) => (id, Err(RpcError::new(INVALID_REQUEST, e))),
+                    Ok(request) if request.jsonrpc != "2.0" => {
+                        (id, Err(RpcError::new(INVALID_REQUEST, "expected jsonrpc version 2.0")))
+                    }
+                    Ok(request) => {
+                        let result = self.dispatch(&request.method, request.params);
+                        (request.id?, result)
+                    }
+                }
+            }
+        };
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        Some(Response { jsonrpc: "2.0", id, result, error })
+    }
+}
+
+// Serves requests until the reader is exhausted, see docs/rpc.md for the methods.
+pub fn serve(
+    path: &Path,
+    storage_manager: &mut StorageManager,
+    review_settings: &ReviewSettings,
+    reader: impl BufRead,
+    mut writer: impl Write,
+) -> Result<()> {
+    let mut server = Server { path, storage_manager, review_settings };
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(response) = server.handle_message(&line) else { continue };
+        writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -1,13 +1,15 @@
 use std::path::PathBuf;
 
-use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::error::Result;
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Settings {
     pub output: OutputSettings,
     pub storage: StorageSettings,
+    pub review: ReviewSettings,
 }
 
 impl Settings {
@@ -24,10 +26,10 @@ impl Settings {
             .add_source(Config::try_from(&Settings::default())?)
             .add_source(config::File::from(config_path))
             .add_source(config::Environment::with_prefix("LOSRS").separator("__"))
-            .build()
-            .unwrap();
+            .build()?;
 
         let config: Settings = settings.try_deserialize()?;
+        config.output.check_finite()?;
         Ok(config)
     }
 
@@ -49,20 +51,53 @@ impl Settings {
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub enum OutputFormat {
+    Auto,
     Clean,
     Typst,
     Logseq,
+    Html,
+    Ansi,
     Sixel,
     Kitty,
     ITerm,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl OutputFormat {
+    // Formats rendered through typst into a PNG.
+    pub fn is_image(&self) -> bool {
+        matches!(self, OutputFormat::Sixel | OutputFormat::Kitty | OutputFormat::ITerm)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Auto,
+    Light,
+    Dark,
+}
+
+// Hex colors like "#1e1e2e", overriding the ones of the theme when set.
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct ThemeColors {
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+    pub accent: Option<String>,
+    pub code_background: Option<String>,
+    pub quote_bar: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct OutputSettings {
     pub format: OutputFormat,
     pub ppi: f32,
     pub base_font_size: i32,
     pub line_height_scaling: f32,
+    pub min_font_scaling: f32,
+    pub max_reference_depth: u32,
+    pub theme: Theme,
+    #[serde(default)]
+    pub colors: ThemeColors,
 }
 
 impl Default for OutputSettings {
@@ -72,10 +107,35 @@ impl Default for OutputSettings {
             ppi: 96.0,
             base_font_size: 12,
             line_height_scaling: 1.2,
+            min_font_scaling: 0.7,
+            max_reference_depth: 5,
+            theme: Theme::Auto,
+            colors: ThemeColors::default(),
         }
     }
 }
 
+impl OutputSettings {
+    // TOML and the environment both accept `nan` and `inf`, which no card can be rendered with.
+    fn check_finite(&self) -> Result<()> {
+        let fields = [
+            ("ppi", self.ppi),
+            ("line_height_scaling", self.line_height_scaling),
+            ("min_font_scaling", self.min_font_scaling),
+        ];
+        for (name, value) in fields {
+            if !value.is_finite() {
+                return Err(config::ConfigError::Message(format!(
+                    "output.{} must be a finite number, got {}",
+                    name, value
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub enum MetadataMode {
@@ -93,3 +153,16 @@ impl Default for StorageSettings {
         Self { metadata_mode: MetadataMode::InGraphRoot }
     }
 }
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ReviewSettings {
+    pub new_cards_per_day: u32,
+    pub max_reviews_per_day: u32,
+    pub prerender_cards: u32,
+}
+
+impl Default for ReviewSettings {
+    fn default() -> Self {
+        Self { new_cards_per_day: 20, max_reviews_per_day: 200, prerender_cards: 2 }
+    }
+}
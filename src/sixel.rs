@@ -0,0 +1,194 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use image::RgbaImage;
+
+// Same as img2sixel's default, and the most terminals support.
+const MAX_COLORS: usize = 256;
+
+type Rgb = [u8; 3];
+
+// A box of the median cut, colors with their pixel counts.
+struct ColorBox {
+    colors: Vec<(Rgb, u32)>,
+}
+
+impl ColorBox {
+    fn channel_range(&self, channel: usize) -> u8 {
+        let values = self.colors.iter().map(|(rgb, _)| rgb[channel]);
+        values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+    }
+
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3).map(|c| (c, self.channel_range(c))).max_by_key(|(_, range)| *range).unwrap()
+    }
+
+    fn pixel_count(&self) -> u64 {
+        self.colors.iter().map(|(_, count)| u64::from(*count)).sum()
+    }
+
+    // Splits at the median pixel, not the median color,
+    // so that colors covering more of the image get more palette entries.
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        // Ties are broken by the whole color, so that the split does not depend on hashing.
+        self.colors.sort_unstable_by_key(|(rgb, _)| (rgb[channel], *rgb));
+        let half = self.pixel_count() / 2;
+        let mut seen = 0;
+        let mut at = 1;
+        for (i, (_, count)) in self.colors.iter().enumerate() {
+            seen += u64::from(*count);
+            if seen >= half {
+                at = i + 1;
+                break;
+            }
+        }
+        let at = at.clamp(1, self.colors.len() - 1);
+        let upper = self.colors.split_off(at);
+        (self, ColorBox { colors: upper })
+    }
+
+    fn average(&self) -> Rgb {
+        let total = self.pixel_count().max(1);
+        let mut sums = [0u64; 3];
+        for (rgb, count) in &self.colors {
+            for c in 0..3 {
+                sums[c] += u64::from(rgb[c]) * u64::from(*count);
+            }
+        }
+        sums.map(|sum| ((sum + total / 2) / total) as u8)
+    }
+}
+
+fn median_cut(histogram: HashMap<Rgb, u32>, max_colors: usize) -> Vec<Rgb> {
+    if histogram.len() <= max_colors {
+        // Most used first, in the same order on every run, so that the same image encodes the same.
+        let mut colors: Vec<(Rgb, u32)> = histogram.into_iter().collect();
+        colors.sort_unstable_by_key(|(rgb, count)| (Reverse(*count), *rgb));
+        return colors.into_iter().map(|(rgb, _)| rgb).collect();
+    }
+    let mut boxes = vec![ColorBox { colors: histogram.into_iter().collect() }];
+    while boxes.len() < max_colors {
+        // The box spanning the widest range, weighted by how much of the image it covers.
+        let Some((i, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.colors.len() > 1)
+            .max_by_key(|(_, b)| u64::from(b.widest_channel().1) * b.pixel_count())
+        else {
+            break;
+        };
+        let (lower, upper) = boxes.swap_remove(i).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    boxes.iter().map(ColorBox::average).collect()
+}
+
+fn nearest(palette: &[Rgb], rgb: Rgb) -> u8 {
+    let distance =
+        |p: &Rgb| -> i32 { (0..3).map(|c| (i32::from(p[c]) - i32::from(rgb[c])).pow(2)).sum() };
+    let (i, _) = palette.iter().enumerate().min_by_key(|(_, p)| distance(p)).unwrap();
+    i as u8
+}
+
+// Maps pixels onto the palette with Floyd-Steinberg error diffusion,
+// transparent pixels are None.
+fn dither(image: &RgbaImage, palette: &[Rgb]) -> Vec<Option<u8>> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut indices = Vec::with_capacity(width * height);
+    let mut nearest_cache: HashMap<Rgb, u8> = HashMap::new();
+    let mut errors = vec![[0f32; 3]; width + 2];
+    let mut next_errors = vec![[0f32; 3]; width + 2];
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel(x as u32, y as u32).0;
+            if pixel[3] < 128 {
+                indices.push(None);
+                continue;
+            }
+            let error = errors[x + 1];
+            let wanted: [f32; 3] = std::array::from_fn(|c| f32::from(pixel[c]) + error[c]);
+            let rgb = wanted.map(|v| v.round().clamp(0.0, 255.0) as u8);
+            let i = *nearest_cache.entry(rgb).or_insert_with(|| nearest(palette, rgb));
+            indices.push(Some(i));
+            let chosen = palette[i as usize];
+            for c in 0..3 {
+                let e = wanted[c] - f32::from(chosen[c]);
+                errors[x + 2][c] += e * 7.0 / 16.0;
+                next_errors[x][c] += e * 3.0 / 16.0;
+                next_errors[x + 1][c] += e * 5.0 / 16.0;
+                next_errors[x + 2][c] += e * 1.0 / 16.0;
+            }
+        }
+        std::mem::swap(&mut errors, &mut next_errors);
+        next_errors.fill([0.0; 3]);
+    }
+    indices
+}
+
+fn write_run(sixel: &mut String, sixel_char: char, run: usize) {
+    match run {
+        0 => {}
+        1..=3 => sixel.extend(std::iter::repeat_n(sixel_char, run)),
+        _ => write!(sixel, "!{}{}", run, sixel_char).unwrap(),
+    }
+}
+
+// Encodes the image as sixel, with up to 256 colors.
+// Transparent pixels are left unpainted, showing the terminal's background.
+pub fn encode_sixel(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut histogram: HashMap<Rgb, u32> = HashMap::new();
+    for pixel in image.pixels().filter(|p| p[3] >= 128) {
+        *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+    }
+    let palette = median_cut(histogram, MAX_COLORS);
+    let indices = dither(image, &palette);
+
+    let mut sixel = String::new();
+    // P2=1 keeps unpainted pixels transparent, the raster attributes set 1:1 aspect ratio.
+    write!(sixel, "\x1bP0;1q\"1;1;{};{}", width, height).unwrap();
+    for (i, [r, g, b]) in palette.iter().enumerate() {
+        let percent = |v: u8| (u32::from(v) * 100 + 127) / 255;
+        write!(sixel, "#{};2;{};{};{}", i, percent(*r), percent(*g), percent(*b)).unwrap();
+    }
+
+    for band_top in (0..height).step_by(6) {
+        let band_rows = (height - band_top).min(6);
+        let band = |x: usize, row: usize| indices[(band_top + row) * width + x];
+        let mut colors: Vec<u8> =
+            (0..width).flat_map(|x| (0..band_rows).filter_map(move |row| band(x, row))).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        for (n, color) in colors.iter().enumerate() {
+            if n > 0 {
+                // Back to the start of the band to paint the next color over it.
+                sixel.push('$');
+            }
+            write!(sixel, "#{}", color).unwrap();
+            let mut run_char = '?';
+            let mut run = 0;
+            for x in 0..width {
+                let bits = (0..band_rows)
+                    .filter(|row| band(x, *row) == Some(*color))
+                    .fold(0u8, |bits, row| bits | (1 << row));
+                let sixel_char = char::from(63 + bits);
+                if sixel_char != run_char {
+                    write_run(&mut sixel, run_char, run);
+                    run_char = sixel_char;
+                    run = 0;
+                }
+                run += 1;
+            }
+            // Nothing needs to be painted after the last pixel of this color.
+            if run_char != '?' {
+                write_run(&mut sixel, run_char, run);
+            }
+        }
+        sixel.push('-');
+    }
+    sixel.push_str("\x1b\\");
+    sixel.into_bytes()
+}
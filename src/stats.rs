@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::DateTime;
+use chrono::Days;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use chrono::TimeDelta;
+use rs_fsrs::Rating;
+use rs_fsrs::State;
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::types::CardMetadata;
+use crate::types::CardReviewLog;
+
+// Number of days shown in the daily activity breakdown.
+const DAILY_ACTIVITY_DAYS: u64 = 7;
+
+#[derive(Serialize, Default)]
+struct CardCounts {
+    total: u32,
+    new: u32,
+    learning: u32,
+    review: u32,
+    relearning: u32,
+}
+
+#[derive(Serialize, Default)]
+struct DueCounts {
+    today: u32,
+    this_week: u32,
+}
+
+// Fraction of reviews of cards in review state that were recalled.
+#[derive(Serialize)]
+struct TrueRetention {
+    last_7_days: Option<f64>,
+    last_30_days: Option<f64>,
+    last_365_days: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct DailyActivity {
+    date: NaiveDate,
+    reviews: u32,
+    time_spent_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    cards: CardCounts,
+    due: DueCounts,
+    average_stability: Option<f64>,
+    average_difficulty: Option<f64>,
+    // None when there is no review history, see `storage.metadata_mode`.
+    true_retention: Option<TrueRetention>,
+    daily_activity: Option<Vec<DailyActivity>>,
+}
+
+#[derive(Serialize)]
+pub struct StatsReport {
+    #[serde(flatten)]
+    total: Stats,
+    pages: BTreeMap<Arc<PathBuf>, Stats>,
+}
+
+fn average(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 { None } else { Some(sum / count as f64) }
+}
+
+fn compute_true_retention(
+    card_review_logs: &[&CardReviewLog],
+    since: DateTime<FixedOffset>,
+) -> Option<f64> {
+    let (recalled, total) = card_review_logs
+        .iter()
+        .map(|crl| &crl.review_log)
+        .filter(|rl| rl.state == State::Review && rl.reviewed_date >= since)
+        .fold((0, 0), |(recalled, total), rl| {
+            (recalled + (rl.rating != Rating::Again) as u32, total + 1)
+        });
+    if total == 0 { None } else { Some(recalled as f64 / total as f64) }
+}
+
+fn compute_daily_activity(
+    card_review_logs: &[&CardReviewLog],
+    at: DateTime<FixedOffset>,
+) -> Vec<DailyActivity> {
+    let today = at.date_naive();
+    let mut daily_activity: Vec<DailyActivity> = (0..DAILY_ACTIVITY_DAYS)
+        .rev()
+        .map(|days_ago| DailyActivity {
+            date: today - Days::new(days_ago),
+            reviews: 0,
+            time_spent_ms: 0,
+        })
+        .collect();
+    for crl in card_review_logs {
+        let date = crl.review_log.reviewed_date.with_timezone(at.offset()).date_naive();
+        let Some(da) = daily_activity.iter_mut().find(|da| da.date == date) else {
+            continue;
+        };
+        da.reviews += 1;
+        da.time_spent_ms += crl.review_duration_ms.unwrap_or_default();
+    }
+    daily_activity
+}
+
+impl Stats {
+    fn new(
+        card_metas: &[&CardMetadata],
+        card_review_logs: Option<&[&CardReviewLog]>,
+        at: DateTime<FixedOffset>,
+    ) -> Self {
+        let mut cards = CardCounts::default();
+        let mut due = DueCounts::default();
+        // "Today" and "this week" end at midnight in the timezone of `at`.
+        let end_of_today = at.date_naive() + Days::new(1);
+        let end_of_week = at.date_naive() + Days::new(7);
+        for fsrs_meta in card_metas.iter().map(|cm| &cm.srs_meta.fsrs_meta) {
+            cards.total += 1;
+            match fsrs_meta.state {
+                State::New => cards.new += 1,
+                State::Learning => cards.learning += 1,
+                State::Review => cards.review += 1,
+                State::Relearning => cards.relearning += 1,
+            }
+            let due_date = fsrs_meta.due.with_timezone(at.offset()).date_naive();
+            if due_date < end_of_today {
+                due.today += 1;
+            }
+            if due_date < end_of_week {
+                due.this_week += 1;
+            }
+        }
+
+        let reviewed_fsrs_metas = || {
+            card_metas.iter().map(|cm| &cm.srs_meta.fsrs_meta).filter(|fm| fm.state != State::New)
+        };
+
+        Self {
+            cards,
+            due,
+            average_stability: average(reviewed_fsrs_metas().map(|fm| fm.stability)),
+            average_difficulty: average(reviewed_fsrs_metas().map(|fm| fm.difficulty)),
+            true_retention: card_review_logs.map(|crls| TrueRetention {
+                last_7_days: compute_true_retention(crls, at - TimeDelta::days(7)),
+                last_30_days: compute_true_retention(crls, at - TimeDelta::days(30)),
+                last_365_days: compute_true_retention(crls, at - TimeDelta::days(365)),
+            }),
+            daily_activity: card_review_logs.map(|crls| compute_daily_activity(crls, at)),
+        }
+    }
+}
+
+impl StatsReport {
+    pub fn new(
+        card_metas: &[CardMetadata],
+        card_review_logs: Option<&[CardReviewLog]>,
+        at: DateTime<FixedOffset>,
+    ) -> Self {
+        // Review history covers the whole graph, only keep the reviews of selected cards.
+        let source_path_by_csn: HashMap<u64, &Arc<PathBuf>> = card_metas
+            .iter()
+            .filter_map(|cm| cm.card_ref.serial_num.map(|csn| (csn, &cm.card_ref.source_path)))
+            .collect();
+        let card_review_logs: Option<Vec<&CardReviewLog>> = card_review_logs.map(|crls| {
+            crls.iter().filter(|crl| source_path_by_csn.contains_key(&crl.serial_num)).collect()
+        });
+
+        let mut card_metas_by_page: BTreeMap<Arc<PathBuf>, Vec<&CardMetadata>> = BTreeMap::new();
+        for cm in card_metas {
+            card_metas_by_page.entry(cm.card_ref.source_path.clone()).or_default().push(cm);
+        }
+
+        let pages = card_metas_by_page
+            .into_iter()
+            .map(|(source_path, page_card_metas)| {
+                let page_card_review_logs: Option<Vec<&CardReviewLog>> =
+                    card_review_logs.as_ref().map(|crls| {
+                        crls.iter()
+                            .filter(|crl| source_path_by_csn[&crl.serial_num] == &source_path)
+                            .copied()
+                            .collect()
+                    });
+                let stats = Stats::new(&page_card_metas, page_card_review_logs.as_deref(), at);
+                (source_path, stats)
+            })
+            .collect();
+
+        let all_card_metas: Vec<&CardMetadata> = card_metas.iter().collect();
+        Self { total: Stats::new(&all_card_metas, card_review_logs.as_deref(), at), pages }
+    }
+}
+
+fn format_optional(value: Option<f64>, format: impl Fn(f64) -> String) -> String {
+    value.map(format).unwrap_or_else(|| "n/a".to_owned())
+}
+
+fn format_percentage(value: Option<f64>) -> String {
+    format_optional(value, |v| format!("{:.1}%", v * 100.0))
+}
+
+fn format_time_spent(ms: u64) -> String {
+    let secs = ms / 1000;
+    match (secs / 3600, (secs % 3600) / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+fn format_stats(mut writer: impl std::io::Write, stats: &Stats, indent: &str) -> Result<()> {
+    let cards = &stats.cards;
+    writeln!(
+        writer,
+        "{indent}Cards: {} (new {}, learning {}, review {}, relearning {})",
+        cards.total, cards.new, cards.learning, cards.review, cards.relearning
+    )?;
+    writeln!(writer, "{indent}Due: {} today, {} this week", stats.due.today, stats.due.this_week)?;
+    writeln!(
+        writer,
+        "{indent}Average stability: {}, average difficulty: {}",
+        format_optional(stats.average_stability, |v| format!("{:.2} days", v)),
+        format_optional(stats.average_difficulty, |v| format!("{:.2}", v)),
+    )?;
+    let Some(true_retention) = &stats.true_retention else {
+        writeln!(writer, "{indent}No review history, use in-graph-root storage.metadata_mode")?;
+        return Ok(());
+    };
+    writeln!(
+        writer,
+        "{indent}True retention: {} (7 days), {} (30 days), {} (365 days)",
+        format_percentage(true_retention.last_7_days),
+        format_percentage(true_retention.last_30_days),
+        format_percentage(true_retention.last_365_days),
+    )?;
+    if let Some(daily_activity) = &stats.daily_activity {
+        writeln!(writer, "{indent}Reviews in the last {} days:", DAILY_ACTIVITY_DAYS)?;
+        for da in daily_activity {
+            writeln!(
+                writer,
+                "{indent}  {}: reviews: {}, time spent: {}",
+                da.date,
+                da.reviews,
+                format_time_spent(da.time_spent_ms)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn show_stats(stats_report: &StatsReport, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(stats_report)?);
+        return Ok(());
+    }
+    let mut stdout = std::io::stdout();
+    format_stats(&mut stdout, &stats_report.total, "")?;
+    for (source_path, stats) in &stats_report.pages {
+        println!();
+        println!("{}", source_path.display());
+        format_stats(&mut stdout, stats, "  ")?;
+    }
+    Ok(())
+}
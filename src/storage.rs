@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::fs::OpenOptions;
@@ -8,14 +9,14 @@ use std::io::Seek;
 use std::io::SeekFrom;
 use std::io::Write;
 use std::ops::RangeInclusive;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
-use std::rc::Rc;
+use std::sync::Arc;
 use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::time::Duration;
 
-use anyhow::Context;
-use anyhow::Result;
-use anyhow::anyhow;
 use chrono::DateTime;
 
 use markdown::ParseOptions;
@@ -25,9 +26,15 @@ use markdown::to_mdast;
 use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
 
+use crate::error::Error;
+use crate::error::PathContext;
+use crate::error::Result;
 use crate::output::CardBodyParts;
 use crate::output::format_card_logseq;
+use crate::references::GraphIndex;
+use crate::references::LazyGraphIndex;
 use crate::settings::MetadataMode;
 use crate::settings::StorageSettings;
 use crate::types::Card;
@@ -35,8 +42,10 @@ use crate::types::CardBody;
 use crate::types::CardId;
 use crate::types::CardMetadata;
 use crate::types::CardRef;
+use crate::types::CardReviewLog;
 use crate::types::FSRSMeta;
 use crate::types::LogseqSRSMeta;
+use crate::types::ReviewLog;
 use crate::types::SRSMeta;
 
 fn list_item_is_card(li: &mdast::ListItem) -> bool {
@@ -76,11 +85,14 @@ fn list_item_is_card(li: &mdast::ListItem) -> bool {
     false
 }
 
-fn find_card_list_items(file_raw: &str) -> Result<Vec<mdast::ListItem>> {
+// Parsing problems are reported along with the page path by Page.
+type ParseResult<T> = std::result::Result<T, String>;
+
+fn find_card_list_items(file_raw: &str) -> ParseResult<Vec<mdast::ListItem>> {
     let tree = to_mdast(file_raw, &ParseOptions::default())
-        .map_err(|x| anyhow!("could not parse markdown: {:?}", x))?;
+        .map_err(|x| format!("could not parse markdown: {:?}", x))?;
     let Node::Root(r) = tree else {
-        return Err(anyhow!("expected Root node, got: {:?}", tree));
+        return Err(format!("expected Root node, got: {:?}", tree));
     };
     let top_list = match r.children.as_slice() {
         [Node::Paragraph(_)] | [] => {
@@ -91,7 +103,7 @@ fn find_card_list_items(file_raw: &str) -> Result<Vec<mdast::ListItem>> {
         [Node::Paragraph(_), Node::List(l)] => l,
         [Node::List(l)] => l,
         top_nodes => {
-            return Err(anyhow!("expected (Paragraph,)? List, got: {:?}", top_nodes));
+            return Err(format!("expected (Paragraph,)? List, got: {:?}", top_nodes));
         }
     };
     Ok(find_card_list_items_inner(top_list))
@@ -129,13 +141,13 @@ struct CardLineRanges {
     response_range: RangeInclusive<usize>,
 }
 
-fn find_card_ranges(card: &mdast::ListItem) -> Result<CardLineRanges> {
+fn find_card_ranges(card: &mdast::ListItem) -> ParseResult<CardLineRanges> {
     // TODO: allow multiple paragraphs followed by a list
     // take until list?
     let (prompt_paragraph, response_list) = match card.children.as_slice() {
         [Node::Paragraph(p), Node::List(l)] => (p, l),
         _ => {
-            return Err(anyhow!(
+            return Err(format!(
                 "Expected card children to be [Paragraph, List], got {:?}",
                 card.children
             ));
@@ -145,13 +157,13 @@ fn find_card_ranges(card: &mdast::ListItem) -> Result<CardLineRanges> {
     let p_position = prompt_paragraph
         .position
         .as_ref()
-        .ok_or_else(|| anyhow!("The p somehow didn't have a position"))?;
+        .ok_or_else(|| "The p somehow didn't have a position".to_owned())?;
     let p_range = range_from_position(p_position);
 
     let l_position = response_list
         .position
         .as_ref()
-        .ok_or_else(|| anyhow!("The p somehow didn't have a position"))?;
+        .ok_or_else(|| "The p somehow didn't have a position".to_owned())?;
     let l_range = range_from_position(l_position);
 
     Ok(CardLineRanges { prompt_range: p_range, response_range: l_range })
@@ -160,14 +172,14 @@ fn find_card_ranges(card: &mdast::ListItem) -> Result<CardLineRanges> {
 fn destructure_card<'a>(
     card: &mdast::ListItem,
     file_raw_lines: &'a [&'a str],
-) -> Result<(&'a [&'a str], &'a [&'a str])> {
+) -> ParseResult<(&'a [&'a str], &'a [&'a str])> {
     let ranges = find_card_ranges(card)?;
     let Some(prompt_lines) = file_raw_lines.get(ranges.prompt_range) else {
-        return Err(anyhow!("Failed to get prompt lines"));
+        return Err("Failed to get prompt lines".to_owned());
     };
 
     let Some(response_lines) = file_raw_lines.get(ranges.response_range) else {
-        return Err(anyhow!("Failed to get response lines"));
+        return Err("Failed to get response lines".to_owned());
     };
 
     Ok((prompt_lines, response_lines))
@@ -178,15 +190,16 @@ fn is_metadata_line(l: &str) -> bool {
 }
 
 impl SRSMeta {
-    fn from_prompt_lines(prompt_lines: &[&str]) -> Result<Self> {
+    fn from_prompt_lines(prompt_lines: &[&str]) -> ParseResult<Self> {
         let mut logseq_srs_meta = LogseqSRSMeta::default();
         let mut fsrs_meta: Option<FSRSMeta> = None;
+        let mut suspended = false;
 
         for line in prompt_lines {
             let Some((k, v)) = line.trim().split_once(":: ") else {
                 continue;
             };
-            (|| -> Result<()> {
+            (|| -> std::result::Result<(), Box<dyn std::error::Error>> {
                 match k {
                     "card-last-interval" => {
                         logseq_srs_meta.last_interval = v.parse()?;
@@ -209,21 +222,24 @@ impl SRSMeta {
                     "card-fsrs-metadata" => {
                         fsrs_meta = Some(serde_json::from_str(v)?);
                     }
+                    "card-suspended" => {
+                        suspended = v.parse()?;
+                    }
                     _ => {}
                 };
                 Ok(())
             })()
-            .with_context(|| anyhow!("when processing key '{}'", k))?;
+            .map_err(|e| format!("when processing key '{}': {}", k, e))?;
         }
         match fsrs_meta {
             Some(fsrs_meta) => {
                 let logseq_srs_meta: LogseqSRSMeta = (&fsrs_meta).into();
-                Ok(SRSMeta { logseq_srs_meta, fsrs_meta })
+                Ok(SRSMeta { logseq_srs_meta, fsrs_meta, suspended })
             }
             None => {
                 // This case includes "neither metadata is present",
                 let fsrs_meta: FSRSMeta = (&logseq_srs_meta).into();
-                Ok(SRSMeta { logseq_srs_meta, fsrs_meta })
+                Ok(SRSMeta { logseq_srs_meta, fsrs_meta, suspended })
             }
         }
     }
@@ -250,6 +266,53 @@ fn extract_serial_num(prompt: &str) -> Option<u64> {
     CARD_SERIAL_NUM_RE.captures(prompt)?.name("csn").map(|m| m.as_str().parse::<u64>().unwrap())
 }
 
+// Removes the `#card` tag along with the serial number, leaving only the prompt itself.
+fn strip_card_tag(prompt: &str) -> String {
+    CARD_SERIAL_NUM_RE.replace(prompt, "").trim_end().to_owned()
+}
+
+fn unindent_card_text(text: &str) -> String {
+    text.lines().map(|l| l.strip_prefix("  ").unwrap_or(l)).collect::<Vec<_>>().join("\n")
+}
+
+// Conversions between card bodies and plain text, used when exchanging cards with other tools.
+// The prompt starts with the list marker of the card block,
+// the rest of the card is indented one level below it.
+impl CardBody {
+    pub fn from_text(prompt: &str, response: &str) -> Self {
+        let mut prompt_lines = prompt.lines().map(str::trim).filter(|l| !l.is_empty());
+        let mut card_prompt = format!("- {} #card", prompt_lines.next().unwrap_or_default());
+        for line in prompt_lines {
+            card_prompt.push_str(&format!("\n  {}", line));
+        }
+
+        // Lines that are not list items already become list items of their own.
+        let response_lines: Vec<String> = response
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| match l.trim_start().starts_with("- ") {
+                true => format!("  {}", l.trim_end()),
+                false => format!("  - {}", l.trim()),
+            })
+            .collect();
+        // Cards need a response list to be found.
+        let card_response =
+            if response_lines.is_empty() { "  -".to_owned() } else { response_lines.join("\n") };
+
+        CardBody { prompt: card_prompt, prompt_indent: 0, response: card_response }
+    }
+
+    pub fn prompt_text(&self) -> String {
+        let prompt = strip_card_tag(&self.prompt);
+        let prompt = prompt.trim_start().strip_prefix("- ").unwrap_or(&prompt);
+        unindent_card_text(prompt)
+    }
+
+    pub fn response_text(&self) -> String {
+        unindent_card_text(&self.response)
+    }
+}
+
 fn maybe_allocate_serial_num(
     card: &mut Card,
     serial_num_allocator: &mut dyn CardSerialNumAllocator,
@@ -260,13 +323,7 @@ fn maybe_allocate_serial_num(
     let Some(serial_num) = serial_num_allocator.allocate() else {
         return Ok(());
     };
-    let serial_num = serial_num.with_context(|| {
-        anyhow!(
-            "could not allocate serial number for card in {} with fingerprint {}",
-            card.metadata.card_ref.source_path.display(),
-            card.metadata.card_ref.prompt_fingerprint
-        )
-    })?;
+    let serial_num = serial_num?;
     card.metadata.card_ref.serial_num = Some(serial_num);
     card.body.prompt = CARD_SERIAL_NUM_RE
         .replace(&card.body.prompt, format!("#card <!-- CSN:{} -->", serial_num))
@@ -274,26 +331,52 @@ fn maybe_allocate_serial_num(
     Ok(())
 }
 
+// Extracts all cards from the page file.
+pub fn parse_page(page_file: &Path) -> Result<Vec<Card>> {
+    Page::new(page_file)?.extract_cards()
+}
+
+// Like parse_page, but for page contents that are already in memory.
+// page_file is only used to fill in the card refs.
+pub fn parse_page_str(page_file: &Path, page_raw: &str) -> Result<Vec<Card>> {
+    Page::from_raw(page_file, page_raw.to_owned())?.extract_cards()
+}
+
 struct Page {
-    path: Rc<PathBuf>,
+    path: Arc<PathBuf>,
     file_raw: String,
     card_list_items: Vec<mdast::ListItem>,
 }
 
 impl Page {
     fn new(path: &Path) -> Result<Self> {
-        let file_raw = fs::read_to_string(path)?;
+        let file_raw = fs::read_to_string(path).with_path(path)?;
+        Self::from_raw(path, file_raw)
+    }
 
-        let card_list_items = find_card_list_items(&file_raw)
-            .with_context(|| anyhow!("when searching for card list items"))?;
-        Ok(Page { path: Rc::new(path.to_path_buf()), file_raw, card_list_items })
+    fn from_raw(path: &Path, file_raw: String) -> Result<Self> {
+        let card_list_items =
+            find_card_list_items(&file_raw).map_err(|message| Error::InvalidPage {
+                path: path.to_path_buf(),
+                line: None,
+                message: format!("when searching for card list items: {}", message),
+            })?;
+        Ok(Page { path: Arc::new(path.to_path_buf()), file_raw, card_list_items })
+    }
+
+    fn invalid_card_error(&self, card_list_item: &mdast::ListItem, message: String) -> Error {
+        Error::InvalidPage {
+            path: self.path.to_path_buf(),
+            line: card_list_item.position.as_ref().map(|pos| pos.start.line),
+            message: format!("when extracting a card from list item: {}", message),
+        }
     }
 
     fn get_lines(&self) -> Vec<&str> {
         self.file_raw.lines().collect()
     }
 
-    fn extract_card(&self, card_list_item: &mdast::ListItem) -> Result<Card> {
+    fn extract_card(&self, card_list_item: &mdast::ListItem) -> ParseResult<Card> {
         let file_raw_lines = self.get_lines();
         let (prompt_lines, response_lines) = destructure_card(card_list_item, &file_raw_lines)?;
 
@@ -318,7 +401,8 @@ impl Page {
                     serial_num: extract_serial_num(&prompt),
                 },
                 srs_meta: SRSMeta::from_prompt_lines(prompt_lines)
-                    .with_context(|| "when extracting SRS meta")?,
+                    .map_err(|message| format!("when extracting SRS meta: {}", message))?,
+                graph_index: None,
             },
             body: CardBody { prompt, prompt_indent: prompt_indent_size, response },
         })
@@ -327,33 +411,24 @@ impl Page {
     fn extract_cards(&self) -> Result<Vec<Card>> {
         self.card_list_items
             .iter()
-            .map(|li| {
-                self.extract_card(li).with_context(|| {
-                    anyhow!(
-                        "when extracting a card from list item on line {}",
-                        li.position
-                            .as_ref()
-                            .map(|pos| pos.start.line)
-                            .expect("so far list items always have a start...")
-                    )
-                })
-            })
+            .map(|li| self.extract_card(li).map_err(|message| self.invalid_card_error(li, message)))
             .collect()
     }
 
     fn find_card(&self, card_ref: &CardRef) -> Result<(CardLineRanges, Card)> {
         for li in &self.card_list_items {
-            let card = self.extract_card(li)?;
+            let card =
+                self.extract_card(li).map_err(|message| self.invalid_card_error(li, message))?;
             if card.metadata.card_ref.prompt_fingerprint == card_ref.prompt_fingerprint {
-                let card_ranges = find_card_ranges(li)?;
+                let card_ranges =
+                    find_card_ranges(li).map_err(|message| self.invalid_card_error(li, message))?;
                 return Ok((card_ranges, card));
             }
         }
-        Err(anyhow!(
-            "Card with fingerprint {} was not found in {}.",
-            card_ref.prompt_fingerprint,
-            card_ref.source_path.display(),
-        ))
+        Err(Error::CardNotFound {
+            path: card_ref.source_path.to_path_buf(),
+            fingerprint: card_ref.prompt_fingerprint.clone(),
+        })
     }
 
     fn rewrite_card(
@@ -363,23 +438,24 @@ impl Page {
         card_body_parts: CardBodyParts,
     ) -> Result<()> {
         let file_raw_lines = self.get_lines();
-        let mut f = File::create(self.path.as_ref())?;
+        // Written at once, so that a failure does not leave the page truncated.
+        let mut page_raw = Vec::new();
 
         let pre_lines = &file_raw_lines[..*card_ranges.prompt_range.start()];
         if !pre_lines.is_empty() {
-            f.write_all(pre_lines.join("\n").as_bytes())?;
-            f.write_all("\n".as_bytes())?;
+            page_raw.write_all(pre_lines.join("\n").as_bytes())?;
+            page_raw.write_all("\n".as_bytes())?;
         }
 
-        format_card_logseq(card, &f, card_body_parts)?;
+        format_card_logseq(card, &mut page_raw, card_body_parts)?;
 
         let post_lines = &file_raw_lines[*card_ranges.response_range.end() + 1..];
         if !post_lines.is_empty() {
-            f.write_all(post_lines.join("\n").as_bytes())?;
-            f.write_all("\n".as_bytes())?;
+            page_raw.write_all(post_lines.join("\n").as_bytes())?;
+            page_raw.write_all("\n".as_bytes())?;
         }
 
-        Ok(())
+        fs::write(self.path.as_ref(), page_raw).with_path(&self.path)
     }
 }
 
@@ -397,20 +473,20 @@ enum PageFiles {
 
 fn find_page_files_inner(path: &Path) -> Result<PageFiles> {
     if !path.exists() {
-        return Err(anyhow!("{} does not exist", path.display()));
+        return Err(Error::PathNotFound(path.to_path_buf()));
     }
     // [ref:logseq-dir-layout]
     if !path.is_dir() {
         let Some(parent) = path.parent() else { return Ok(PageFiles::Single(path.to_path_buf())) };
-        let parent_name = parent
-            .file_name()
-            .ok_or_else(|| anyhow!("parent of {} does not have a file name", path.display()))?;
-        if parent_name == "pages" {
+        if parent.file_name() == Some(OsStr::new("pages")) {
             // parent is definitely not root, so it definitely has a parent, unwrap is fine.
-            return Ok(PageFiles::SingleInGraphRoot(
-                parent.parent().unwrap().to_path_buf(),
-                path.to_path_buf(),
-            ));
+            let graph_root = parent.parent().unwrap();
+            // `pages/page.md` is relative to the current directory.
+            let graph_root = match graph_root.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => graph_root,
+            };
+            return Ok(PageFiles::SingleInGraphRoot(graph_root.to_path_buf(), path.to_path_buf()));
         } else {
             return Ok(PageFiles::Single(path.to_path_buf()));
         }
@@ -418,12 +494,10 @@ fn find_page_files_inner(path: &Path) -> Result<PageFiles> {
 
     let pages_dir = path.join("pages");
     if !pages_dir.exists() {
-        return Err(anyhow!(
-            "{} is a directory without a pages subdirectory, expected logseq graph root",
-            path.display()
-        ));
+        return Err(Error::NotAGraphRoot(path.to_path_buf()));
     }
-    let page_files = std::fs::read_dir(pages_dir)?
+    let page_files = std::fs::read_dir(&pages_dir)
+        .with_path(&pages_dir)?
         .filter_map(Result::ok)
         .map(|d| d.path())
         .filter(|p| p.is_file() && p.extension() == Some(OsStr::new("md")))
@@ -431,7 +505,7 @@ fn find_page_files_inner(path: &Path) -> Result<PageFiles> {
     Ok(PageFiles::GraphRoot(path.to_path_buf(), page_files))
 }
 
-fn find_graph_root(path: &Path) -> Result<Option<PathBuf>> {
+pub fn find_graph_root(path: &Path) -> Result<Option<PathBuf>> {
     match find_page_files_inner(path)? {
         PageFiles::Single(_) => Ok(None),
         PageFiles::SingleInGraphRoot(graph_root, _) => Ok(Some(graph_root)),
@@ -439,6 +513,38 @@ fn find_graph_root(path: &Path) -> Result<Option<PathBuf>> {
     }
 }
 
+// Resolves `.` and `..` without touching the file system, so `pages/../assets` is `assets`.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Block references and embeds can point to any page of the graph, journals included.
+// `index_root` is the graph root, or a page outside of any graph.
+fn build_graph_index(index_root: &Path) -> GraphIndex {
+    if index_root.is_file() {
+        return GraphIndex::build(&[index_root]);
+    }
+    let page_files: Vec<PathBuf> = ["pages", "journals"]
+        .into_iter()
+        .filter_map(|dir| std::fs::read_dir(index_root.join(dir)).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|d| d.path())
+        .filter(|p| p.is_file() && p.extension() == Some(OsStr::new("md")))
+        .collect();
+    GraphIndex::build(&page_files)
+}
+
 struct NoOpSerialNumAllocator {}
 
 impl CardSerialNumAllocator for NoOpSerialNumAllocator {
@@ -457,23 +563,34 @@ impl GraphRootSerialNumAllocator {
         let card_serial_num_path = self.graph_root.join(".card-serial-num");
 
         if !card_serial_num_path.exists() {
-            let mut card_serial_num_file = File::create(card_serial_num_path)?;
-            card_serial_num_file.write_all(b"0\n")?;
+            fs::write(&card_serial_num_path, b"0\n").with_path(&card_serial_num_path)?;
             return Ok(0);
         }
 
-        let mut card_serial_num_file =
-            OpenOptions::new().read(true).write(true).open(&card_serial_num_path)?;
+        let mut card_serial_num_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&card_serial_num_path)
+            .with_path(&card_serial_num_path)?;
 
         let mut card_serial_num_raw = String::new();
-        card_serial_num_file.read_to_string(&mut card_serial_num_raw)?;
-
-        let mut card_serial: u64 = card_serial_num_raw.trim_end().parse()?;
+        card_serial_num_file
+            .read_to_string(&mut card_serial_num_raw)
+            .with_path(&card_serial_num_path)?;
+
+        let mut card_serial: u64 =
+            card_serial_num_raw.trim_end().parse().map_err(|e| Error::InvalidFile {
+                path: card_serial_num_path.clone(),
+                message: format!("expected a card serial number: {}", e),
+            })?;
         card_serial += 1;
 
-        card_serial_num_file.seek(SeekFrom::Start(0))?;
-        card_serial_num_file.set_len(0)?;
-        card_serial_num_file.write_all(format!("{}\n", card_serial).as_bytes())?;
+        (|| {
+            card_serial_num_file.seek(SeekFrom::Start(0))?;
+            card_serial_num_file.set_len(0)?;
+            card_serial_num_file.write_all(format!("{}\n", card_serial).as_bytes())
+        })()
+        .with_path(&card_serial_num_path)?;
 
         Ok(card_serial)
     }
@@ -481,10 +598,7 @@ impl GraphRootSerialNumAllocator {
 
 impl CardSerialNumAllocator for GraphRootSerialNumAllocator {
     fn allocate(&mut self) -> Option<Result<u64>> {
-        Some(
-            self.allocate_inner()
-                .with_context(|| anyhow!("failed to read/write card serial number")),
-        )
+        Some(self.allocate_inner())
     }
 }
 
@@ -495,10 +609,34 @@ fn choose_serial_num_allocator(path: &Path) -> Result<Box<dyn CardSerialNumAlloc
     Ok(Box::new(GraphRootSerialNumAllocator { graph_root }))
 }
 
+fn load_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    fs::read_to_string(path)
+        .with_path(path)?
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| Error::InvalidFile {
+                path: path.to_path_buf(),
+                message: format!("line {}: {}", i + 1, e),
+            })
+        })
+        .collect()
+}
+
+fn store_jsonl<T: Serialize>(path: &Path, mut file: File, values: &[T]) -> Result<()> {
+    for v in values {
+        file.write_all(serde_json::to_string(v)?.as_bytes()).with_path(path)?;
+        file.write_all(b"\n").with_path(path)?;
+    }
+    file.sync_all().with_path(path)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct InGraphRootCardMetadata {
     serial_num: u64,
     fsrs_meta: FSRSMeta,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    suspended: bool,
 }
 
 enum MetadataSource {
@@ -509,6 +647,9 @@ enum MetadataSource {
 pub struct StorageManager {
     serial_num_allocator: Box<dyn CardSerialNumAllocator>,
     metadata_source: MetadataSource,
+    // By index root, see `build_graph_index`, built at most once during the manager's lifetime,
+    // so edits made meanwhile to pages referenced by cards show after a restart.
+    graph_indexes: Mutex<HashMap<PathBuf, Arc<LazyGraphIndex>>>,
 }
 
 impl StorageManager {
@@ -517,12 +658,24 @@ impl StorageManager {
             MetadataMode::Inline => MetadataSource::PageFiles,
             MetadataMode::InGraphRoot => {
                 let Some(graph_root) = find_graph_root(path)? else {
-                    return Err(anyhow!("there is no graph root for {}", path.display()));
+                    return Err(Error::NoGraphRoot(path.to_path_buf()));
                 };
                 MetadataSource::GraphRoot(graph_root)
             }
         };
-        Ok(Self { serial_num_allocator: choose_serial_num_allocator(path)?, metadata_source })
+        Ok(Self {
+            serial_num_allocator: choose_serial_num_allocator(path)?,
+            metadata_source,
+            graph_indexes: Mutex::default(),
+        })
+    }
+
+    fn graph_index(&self, index_root: PathBuf) -> Arc<LazyGraphIndex> {
+        let mut graph_indexes = self.graph_indexes.lock().unwrap();
+        let graph_index = graph_indexes.entry(index_root.clone()).or_insert_with(|| {
+            Arc::new(LazyGraphIndex::new(Box::new(move || build_graph_index(&index_root))))
+        });
+        Arc::clone(graph_index)
     }
 
     pub fn find_page_files(&self, path: &Path) -> Result<Vec<PathBuf>> {
@@ -539,6 +692,19 @@ impl StorageManager {
         Ok(card_metadatas)
     }
 
+    // Serial numbers do not change, unlike prompt fingerprints, so they are preferred.
+    pub fn load_card_meta_by_ref(&self, card_ref: &CardRef) -> Result<CardMetadata> {
+        let card_metas = self.load_card_metas(&card_ref.source_path)?;
+        let card_meta = card_metas.into_iter().find(|cm| match card_ref.serial_num {
+            Some(serial_num) => cm.card_ref.serial_num == Some(serial_num),
+            None => cm.card_ref.prompt_fingerprint == card_ref.prompt_fingerprint,
+        });
+        card_meta.ok_or_else(|| Error::CardNotFound {
+            path: card_ref.source_path.to_path_buf(),
+            fingerprint: card_ref.prompt_fingerprint.clone(),
+        })
+    }
+
     pub fn load_card_body_by_ref(&self, card_ref: &CardRef) -> Result<CardBody> {
         let page = Page::new(&card_ref.source_path)?;
         let (_card_ranges, card) = page.find_card(card_ref)?;
@@ -549,7 +715,7 @@ impl StorageManager {
         graph_root.join(".card-metadata.jsonl")
     }
 
-    fn load_fsrs_metas(graph_root: &Path) -> Result<BTreeMap<u64, FSRSMeta>> {
+    fn load_srs_metas(graph_root: &Path) -> Result<BTreeMap<u64, SRSMeta>> {
         let card_metadata_path = Self::get_card_metadata_path(graph_root);
 
         if !card_metadata_path.exists() {
@@ -557,16 +723,22 @@ impl StorageManager {
             return Ok(BTreeMap::new());
         }
 
-        let mut fsrs_metas_by_csn: BTreeMap<u64, FSRSMeta> = BTreeMap::new();
-        for line in fs::read_to_string(&card_metadata_path)?.lines() {
-            let cm: InGraphRootCardMetadata = serde_json::from_str(line)?;
-            fsrs_metas_by_csn.insert(cm.serial_num, cm.fsrs_meta);
-        }
-        Ok(fsrs_metas_by_csn)
+        let card_metas: Vec<InGraphRootCardMetadata> = load_jsonl(&card_metadata_path)?;
+        Ok(card_metas
+            .into_iter()
+            .map(|cm| {
+                let srs_meta = SRSMeta {
+                    logseq_srs_meta: (&cm.fsrs_meta).into(),
+                    fsrs_meta: cm.fsrs_meta,
+                    suspended: cm.suspended,
+                };
+                (cm.serial_num, srs_meta)
+            })
+            .collect())
     }
 
-    fn store_fsrs_metas(graph_root: &Path, fsrs_metas: BTreeMap<u64, FSRSMeta>) -> Result<()> {
-        assert!(!fsrs_metas.is_empty());
+    fn store_srs_metas(graph_root: &Path, srs_metas: BTreeMap<u64, SRSMeta>) -> Result<()> {
+        assert!(!srs_metas.is_empty());
         let card_metadata_path = Self::get_card_metadata_path(graph_root);
 
         let mut card_metadata_file = OpenOptions::new()
@@ -574,25 +746,27 @@ impl StorageManager {
             .truncate(true)
             .create(true)
             .open(&card_metadata_path)
-            .with_context(|| {
-                anyhow!("when opening {} for writing", card_metadata_path.display())
-            })?;
+            .with_path(&card_metadata_path)?;
 
         // BTreeMap guarantees that metadata is written in serial_num order
-        for (csn, fsrs_meta) in fsrs_metas.into_iter() {
-            let v = InGraphRootCardMetadata { serial_num: csn, fsrs_meta };
+        for (csn, srs_meta) in srs_metas.into_iter() {
+            let v = InGraphRootCardMetadata {
+                serial_num: csn,
+                fsrs_meta: srs_meta.fsrs_meta,
+                suspended: srs_meta.suspended,
+            };
             let cm = serde_json::to_string(&v)?;
-            card_metadata_file.write_all(cm.as_bytes())?;
-            card_metadata_file.write_all(b"\n")?;
+            card_metadata_file.write_all(cm.as_bytes()).with_path(&card_metadata_path)?;
+            card_metadata_file.write_all(b"\n").with_path(&card_metadata_path)?;
         }
-        card_metadata_file.sync_all()?;
+        card_metadata_file.sync_all().with_path(&card_metadata_path)?;
 
         Ok(())
     }
 
     fn merge_page_and_graph_root_card_metas(
         page_card_metas: Vec<CardMetadata>,
-        mut graph_root_card_metas_by_csn: BTreeMap<u64, FSRSMeta>,
+        mut graph_root_card_metas_by_csn: BTreeMap<u64, SRSMeta>,
     ) -> Vec<CardMetadata> {
         let mut card_metas: Vec<CardMetadata> = Vec::new();
         for page_card_meta in page_card_metas {
@@ -600,14 +774,11 @@ impl StorageManager {
                 card_metas.push(page_card_meta);
                 continue;
             };
-            let Some(fsrs_meta) = graph_root_card_metas_by_csn.remove(&csn) else {
+            let Some(srs_meta) = graph_root_card_metas_by_csn.remove(&csn) else {
                 card_metas.push(page_card_meta);
                 continue;
             };
-            card_metas.push(CardMetadata {
-                card_ref: page_card_meta.card_ref,
-                srs_meta: SRSMeta { logseq_srs_meta: (&fsrs_meta).into(), fsrs_meta },
-            });
+            card_metas.push(CardMetadata { srs_meta, ..page_card_meta });
         }
         card_metas
     }
@@ -619,20 +790,69 @@ impl StorageManager {
             MetadataSource::GraphRoot(graph_root) => {
                 Ok(Self::merge_page_and_graph_root_card_metas(
                     page_card_metas,
-                    Self::load_fsrs_metas(graph_root)?,
+                    Self::load_srs_metas(graph_root)?,
                 ))
             }
         }
     }
 
-    pub fn rewrite_card_meta(&mut self, card_ref: &CardRef, srs_meta: &SRSMeta) -> Result<()> {
+    // Appends new cards to the end of the page, creating it if needed,
+    // and allocates serial numbers for them.
+    // The cards are updated with the serial numbers and the resulting prompt fingerprints.
+    pub fn add_cards_to_page(&mut self, page_path: &Path, cards: &mut [Card]) -> Result<()> {
+        for card in cards.iter_mut() {
+            maybe_allocate_serial_num(card, self.serial_num_allocator.as_mut())?;
+            card.metadata.card_ref.prompt_fingerprint = card.body.prompt.as_str().into();
+        }
+
+        let page_raw = match page_path.exists() {
+            true => fs::read_to_string(page_path).with_path(page_path)?,
+            false => String::new(),
+        };
+        let mut cards_raw = Vec::new();
+        if !page_raw.is_empty() && !page_raw.ends_with('\n') {
+            cards_raw.push(b'\n');
+        }
+        for card in cards.iter() {
+            let card_body_parts = match &self.metadata_source {
+                MetadataSource::PageFiles => CardBodyParts::ALL,
+                MetadataSource::GraphRoot(_) => CardBodyParts::PROMPT | CardBodyParts::RESPONSE,
+            };
+            format_card_logseq(card, &mut cards_raw, card_body_parts)?;
+        }
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(page_path)
+            .and_then(|mut page_file| page_file.write_all(&cards_raw))
+            .with_path(page_path)?;
+
+        if let MetadataSource::GraphRoot(graph_root) = &self.metadata_source {
+            let mut card_srs_metas_by_csn = Self::load_srs_metas(graph_root)?;
+            for card in cards.iter() {
+                let csn = card.metadata.card_ref.serial_num.unwrap();
+                card_srs_metas_by_csn.insert(csn, card.metadata.srs_meta.clone());
+            }
+            if !card_srs_metas_by_csn.is_empty() {
+                Self::store_srs_metas(graph_root, card_srs_metas_by_csn)?
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the card_ref as it is after the rewrite,
+    // the serial number might have been allocated, changing the prompt fingerprint.
+    pub fn rewrite_card_meta(&mut self, card_ref: &CardRef, srs_meta: &SRSMeta) -> Result<CardRef> {
         let page = Page::new(&card_ref.source_path)?;
         let (card_ranges, mut card) = page.find_card(card_ref)?;
         card.metadata.srs_meta = srs_meta.clone();
         maybe_allocate_serial_num(&mut card, self.serial_num_allocator.as_mut())?;
 
         match &self.metadata_source {
-            MetadataSource::PageFiles => page.rewrite_card(&card, &card_ranges, CardBodyParts::ALL),
+            MetadataSource::PageFiles => {
+                page.rewrite_card(&card, &card_ranges, CardBodyParts::ALL)?
+            }
             MetadataSource::GraphRoot(graph_root) => {
                 page.rewrite_card(
                     &card,
@@ -641,11 +861,96 @@ impl StorageManager {
                 )?;
                 let csn = card.metadata.card_ref.serial_num.unwrap();
 
-                let mut card_fsrs_metas_by_csn = Self::load_fsrs_metas(graph_root)?;
-                card_fsrs_metas_by_csn.insert(csn, srs_meta.fsrs_meta.clone());
-                Self::store_fsrs_metas(graph_root, card_fsrs_metas_by_csn)
+                let mut card_srs_metas_by_csn = Self::load_srs_metas(graph_root)?;
+                card_srs_metas_by_csn.insert(csn, srs_meta.clone());
+                Self::store_srs_metas(graph_root, card_srs_metas_by_csn)?
             }
+        };
+
+        Ok(CardRef {
+            prompt_fingerprint: card.body.prompt.as_str().into(),
+            ..card.metadata.card_ref
+        })
+    }
+
+    fn get_card_review_log_path(graph_root: &Path) -> PathBuf {
+        graph_root.join(".card-review-log.jsonl")
+    }
+
+    // None means there is no review history to load,
+    // because metadata is not stored in graph root.
+    pub fn load_review_logs(&self) -> Result<Option<Vec<CardReviewLog>>> {
+        let MetadataSource::GraphRoot(graph_root) = &self.metadata_source else {
+            return Ok(None);
+        };
+        let card_review_log_path = Self::get_card_review_log_path(graph_root);
+
+        if !card_review_log_path.exists() {
+            // Will create on first write
+            return Ok(Some(Vec::new()));
         }
+
+        Ok(Some(load_jsonl(&card_review_log_path)?))
+    }
+
+    pub fn append_review_log(
+        &self,
+        card_ref: &CardRef,
+        review_log: &ReviewLog,
+        review_duration: Duration,
+    ) -> Result<()> {
+        let serial_num = card_ref.serial_num.ok_or_else(|| Error::MissingSerialNum {
+            path: card_ref.source_path.to_path_buf(),
+            fingerprint: card_ref.prompt_fingerprint.clone(),
+        })?;
+        self.append_review_logs(&[CardReviewLog {
+            serial_num,
+            review_log: review_log.clone(),
+            review_duration_ms: Some(review_duration.as_millis() as u64),
+        }])
+    }
+
+    pub fn append_review_logs(&self, card_review_logs: &[CardReviewLog]) -> Result<()> {
+        let MetadataSource::GraphRoot(graph_root) = &self.metadata_source else {
+            // Review history is only kept alongside graph root metadata
+            return Ok(());
+        };
+        let card_review_log_path = Self::get_card_review_log_path(graph_root);
+
+        let card_review_log_file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&card_review_log_path)
+            .with_path(&card_review_log_path)?;
+        store_jsonl(&card_review_log_path, card_review_log_file, card_review_logs)
+    }
+
+    // Removes the most recent review log entry for the card, used when undoing a review.
+    pub fn remove_last_review_log(&self, card_ref: &CardRef) -> Result<()> {
+        let MetadataSource::GraphRoot(graph_root) = &self.metadata_source else {
+            return Ok(());
+        };
+        let Some(serial_num) = card_ref.serial_num else {
+            // Cards without serial numbers were never logged
+            return Ok(());
+        };
+        let Some(mut card_review_logs) = self.load_review_logs()? else {
+            return Ok(());
+        };
+        let Some(last_idx) = card_review_logs.iter().rposition(|crl| crl.serial_num == serial_num)
+        else {
+            return Ok(());
+        };
+        card_review_logs.remove(last_idx);
+
+        let card_review_log_path = Self::get_card_review_log_path(graph_root);
+        let card_review_log_file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&card_review_log_path)
+            .with_path(&card_review_log_path)?;
+        store_jsonl(&card_review_log_path, card_review_log_file, &card_review_logs)
     }
 
     pub fn select_card_metadata(
@@ -653,12 +958,15 @@ impl StorageManager {
         path: &Path,
         card_id: Option<CardId>,
     ) -> Result<Vec<CardMetadata>> {
-        let page_files: Vec<PathBuf> = self.find_page_files(path)?;
+        let (index_root, page_files) = match find_page_files_inner(path)? {
+            PageFiles::Single(page_path) => (page_path.clone(), vec![page_path]),
+            PageFiles::SingleInGraphRoot(graph_root, page_path) => (graph_root, vec![page_path]),
+            PageFiles::GraphRoot(graph_root, page_paths) => (graph_root, page_paths),
+        };
+        let graph_index = self.graph_index(index_root);
         let mut all_card_metadatas: Vec<CardMetadata> = Vec::new();
         for page_file in page_files.into_iter() {
-            // avoid copying page_file just so we can print it later
-            let context = format!("when extracting card metadatas from {}", &page_file.display());
-            let mut card_metadatas = self.load_card_metas(&page_file).with_context(|| context)?;
+            let mut card_metadatas = self.load_card_metas(&page_file)?;
 
             if let Some(card_id) = card_id.clone() {
                 let p: Box<dyn Fn(&CardMetadata) -> bool> = match &card_id {
@@ -671,6 +979,9 @@ impl StorageManager {
                 };
                 card_metadatas.retain(p);
             }
+            for cm in &mut card_metadatas {
+                cm.graph_index = Some(graph_index.clone());
+            }
             all_card_metadatas.extend(card_metadatas);
         }
         Ok(all_card_metadatas)
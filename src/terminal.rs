@@ -1,30 +1,8 @@
-use std::io::Write;
-use std::io::stdout;
-
-use anyhow::Result;
-use anyhow::anyhow;
-
-use crossterm::QueueableCommand;
-use crossterm::cursor::MoveTo;
-use crossterm::event::Event;
 use crossterm::event::KeyCode;
 use crossterm::event::KeyEvent;
 use crossterm::event::KeyModifiers;
-use crossterm::event::{self};
-use crossterm::terminal::Clear;
-use crossterm::terminal::ClearType;
-use crossterm::terminal::disable_raw_mode;
-use crossterm::terminal::enable_raw_mode;
-
-pub fn clear_screen() -> Result<()> {
-    let mut stdout = stdout();
-    stdout.queue(MoveTo(0, 0))?;
-    stdout.queue(Clear(ClearType::All))?;
-    stdout.flush()?;
-    Ok(())
-}
 
-fn is_escape_request(key_event: &KeyEvent) -> bool {
+pub fn is_escape_request(key_event: &KeyEvent) -> bool {
     match key_event.code {
         KeyCode::Esc => true,
         KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => true,
@@ -32,71 +10,74 @@ fn is_escape_request(key_event: &KeyEvent) -> bool {
     }
 }
 
-struct RawModeGuard {}
+const ESCAPE_INSTRUCTIONS: &str = "Ctrl+C or Esc to nope out";
+const DETAILS_INSTRUCTIONS: &str = "I - card details; ";
 
-impl RawModeGuard {
-    fn install() -> Self {
-        enable_raw_mode().expect("I do not yet understand how this can fail...");
-        RawModeGuard {}
-    }
+// The page of a card shown, cards taller than the terminal are split into pages.
+#[derive(Debug, Clone, Copy)]
+pub struct Pages {
+    pub current: usize,
+    pub count: usize,
 }
 
-impl Drop for RawModeGuard {
-    fn drop(&mut self) {
-        disable_raw_mode().expect("I do not yet understand how this can fail...");
+impl Pages {
+    fn instructions(&self) -> String {
+        match self.count {
+            0 | 1 => String::new(),
+            _ => format!("←/→ - page {} of {}; ", self.current + 1, self.count),
+        }
     }
-}
 
-#[derive(Debug)]
-pub struct NopeOutError();
-
-impl std::fmt::Display for NopeOutError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("Immediate nope out requested")
+    fn turn(&self, key_event: &KeyEvent) -> Option<PageTurn> {
+        if self.count <= 1 {
+            return None;
+        }
+        match key_event.code {
+            KeyCode::Left | KeyCode::PageUp => Some(PageTurn::Previous),
+            KeyCode::Right | KeyCode::PageDown => Some(PageTurn::Next),
+            _ => None,
+        }
     }
 }
-impl std::error::Error for NopeOutError {}
 
-fn grab_key_event() -> Result<KeyEvent> {
-    let event: Event;
-    {
-        let _raw_mode_guard = RawModeGuard::install();
-        event = event::read()?;
-    }
-    println!();
-    let key_event = match event {
-        Event::Key(key_event) => key_event,
-        _ => return Err(anyhow!("expected a key event, got {:?}", event)),
-    };
-    if is_escape_request(&key_event) { Err((NopeOutError {}).into()) } else { Ok(key_event) }
+#[derive(Debug, PartialEq)]
+pub enum PageTurn {
+    Previous,
+    Next,
 }
 
-const ESCAPE_INSTRUCTIONS: &str = "Ctrl+C or Esc to nope out";
+#[derive(Debug, PartialEq)]
+pub enum Paged<T> {
+    Answer(T),
+    Turn(PageTurn),
+}
 
 #[derive(Debug, PartialEq)]
 pub enum PreReviewResponse {
     ShowResponse,
     DelayReview,
+    UndoPrevious,
 }
 
-pub fn wait_for_prereview() -> Result<PreReviewResponse> {
-    print!("\nSpace - show the response; D - delay review by 1 day; {ESCAPE_INSTRUCTIONS}");
-    stdout().flush()?;
-
-    // BUG: key grabbing should be inside the while loop
-    let key_event = grab_key_event()?;
-
-    let mut tries = 3;
+pub fn prereview_instructions(pages: Pages) -> String {
+    format!(
+        "{}{}Space - show the response; D - delay review by 1 day; U - undo previous answer; {ESCAPE_INSTRUCTIONS}",
+        DETAILS_INSTRUCTIONS,
+        pages.instructions()
+    )
+}
 
-    while tries > 0 {
-        match key_event.code {
-            KeyCode::Char(' ') => return Ok(PreReviewResponse::ShowResponse),
-            KeyCode::Char('d') => return Ok(PreReviewResponse::DelayReview),
-            _ => tries -= 1,
-        };
+// Keys that mean nothing before the response is shown are ignored.
+pub fn prereview_answer(pages: Pages, key_event: &KeyEvent) -> Option<Paged<PreReviewResponse>> {
+    if let Some(turn) = pages.turn(key_event) {
+        return Some(Paged::Turn(turn));
+    }
+    match key_event.code {
+        KeyCode::Char(' ') => Some(Paged::Answer(PreReviewResponse::ShowResponse)),
+        KeyCode::Char('d') => Some(Paged::Answer(PreReviewResponse::DelayReview)),
+        KeyCode::Char('u') => Some(Paged::Answer(PreReviewResponse::UndoPrevious)),
+        _ => None,
     }
-
-    Err(anyhow!("Did not receive expected answer, aborting"))
 }
 
 #[derive(Debug, PartialEq)]
@@ -107,37 +88,63 @@ pub enum ReviewResponse {
     NoRecall,
 }
 
-pub fn wait_for_review() -> Result<ReviewResponse> {
-    print!(
-        r#"
-How much effort did recall require?
-(1 - Little Effort; 2 - Some effort; 3 - Much Effort; 4 - Did not recall; {ESCAPE_INSTRUCTIONS})"#
-    );
-    stdout().flush()?;
+impl ReviewResponse {
+    // In the order of their keys, from 1 to 4.
+    pub const ALL: [ReviewResponse; 4] = [
+        ReviewResponse::LittleEffort,
+        ReviewResponse::SomeEffort,
+        ReviewResponse::MuchEffort,
+        ReviewResponse::NoRecall,
+    ];
+}
 
-    let key_event = grab_key_event()?;
+pub const REVIEW_QUESTION: &str = "How much effort did recall require?";
 
-    let mut tries = 3;
+pub fn review_instructions(pages: Pages) -> String {
+    format!(
+        "({}{}1 - Little Effort; 2 - Some effort; 3 - Much Effort; 4 - Did not recall; {ESCAPE_INSTRUCTIONS})",
+        DETAILS_INSTRUCTIONS,
+        pages.instructions()
+    )
+}
 
-    while tries > 0 {
-        match key_event.code {
-            KeyCode::Char('1') => return Ok(ReviewResponse::LittleEffort),
-            KeyCode::Char('2') => return Ok(ReviewResponse::SomeEffort),
-            KeyCode::Char('3') => return Ok(ReviewResponse::MuchEffort),
-            KeyCode::Char('4') => return Ok(ReviewResponse::NoRecall),
-            _ => tries -= 1,
-        };
+// Keys that mean nothing once the response is shown are ignored.
+pub fn review_answer(pages: Pages, key_event: &KeyEvent) -> Option<Paged<ReviewResponse>> {
+    if let Some(turn) = pages.turn(key_event) {
+        return Some(Paged::Turn(turn));
+    }
+    match key_event.code {
+        KeyCode::Char('1') => Some(Paged::Answer(ReviewResponse::LittleEffort)),
+        KeyCode::Char('2') => Some(Paged::Answer(ReviewResponse::SomeEffort)),
+        KeyCode::Char('3') => Some(Paged::Answer(ReviewResponse::MuchEffort)),
+        KeyCode::Char('4') => Some(Paged::Answer(ReviewResponse::NoRecall)),
+        _ => None,
     }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SessionEndResponse {
+    Finish,
+    UndoPrevious,
+}
+
+pub const SESSION_END_INSTRUCTIONS: &str = "U - undo previous answer; any other key to finish";
 
-    Err(anyhow!("Did not receive expected answer, aborting"))
+// The last answer can still be undone once all cards are done.
+pub fn session_end_answer(key_event: &KeyEvent) -> SessionEndResponse {
+    match key_event.code {
+        KeyCode::Char('u') => SessionEndResponse::UndoPrevious,
+        _ => SessionEndResponse::Finish,
+    }
 }
 
 const DEFAULT_TERM_SIZE: (u16, u16) = (80, 24);
 
 // (columns, lines)
+// Pseudo terminals report a size of 0x0 until one is set.
 pub fn grab_term_size() -> (u16, u16) {
     match crossterm::terminal::size() {
-        Ok(s) => s,
-        Err(_) => DEFAULT_TERM_SIZE,
+        Ok((columns, lines)) if columns > 0 && lines > 0 => (columns, lines),
+        _ => DEFAULT_TERM_SIZE,
     }
 }
@@ -0,0 +1,159 @@
+use std::env;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use crate::settings::OutputFormat;
+
+// Asks about kitty graphics support with a 1x1 image that is never displayed,
+// answered with `APC G i=31 ; OK ST` by terminals that support it.
+const KITTY_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+// Asks for the cell size in pixels, answered with `CSI 6 ; height ; width t`.
+const CELL_SIZE_QUERY: &str = "\x1b[16t";
+// Asks for the background color, answered with `OSC 11 ; rgb:rrrr/gggg/bbbb ST`.
+const BACKGROUND_QUERY: &str = "\x1b]11;?\x1b\\";
+// Primary device attributes, answered with `CSI ? attributes c`, where 4 means sixel.
+// Virtually every terminal answers it, so it is sent last and its answer ends the probe.
+const DA1_QUERY: &str = "\x1b[c";
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Debug)]
+pub struct TerminalProbe {
+    pub format: OutputFormat,
+    // (width, height) of a cell in pixels, when the terminal reports it.
+    pub cell_size: Option<(u16, u16)>,
+    // (red, green, blue) of the background, when the terminal reports it.
+    pub background: Option<(u8, u8, u8)>,
+}
+
+// `CSI ? attribute ; attribute ... c`
+fn da1_attributes(answers: &str) -> Option<Vec<u32>> {
+    let start = answers.find("\x1b[?")? + 3;
+    let end = start + answers[start..].find('c')?;
+    Some(answers[start..end].split(';').filter_map(|attribute| attribute.parse().ok()).collect())
+}
+
+// `CSI 6 ; height ; width t`
+fn reported_cell_size(answers: &str) -> Option<(u16, u16)> {
+    let start = answers.find("\x1b[6;")? + 4;
+    let end = start + answers[start..].find('t')?;
+    let (height, width) = answers[start..end].split_once(';')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+// `OSC 11 ; rgb:r/g/b`, where each component has 1 to 4 hex digits.
+fn reported_background(answers: &str) -> Option<(u8, u8, u8)> {
+    let start = answers.find("\x1b]11;rgb:")? + 9;
+    let end = start + answers[start..].find(['\x1b', '\x07'])?;
+    let mut components = answers[start..end].split('/').map(|component| {
+        if !(1..=4).contains(&component.len()) {
+            return None;
+        }
+        let value = u32::from_str_radix(component, 16).ok()?;
+        let max = 16u32.pow(component.len() as u32) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    Some((components.next()??, components.next()??, components.next()??))
+}
+
+// Terminals that fill in the pixel size of the window, like most do, answer without a query.
+fn window_cell_size() -> Option<(u16, u16)> {
+    let size = crossterm::terminal::window_size().ok()?;
+    if size.columns == 0 || size.rows == 0 || size.width == 0 || size.height == 0 {
+        return None;
+    }
+    Some((size.width / size.columns, size.height / size.rows))
+}
+
+fn env_is(name: &str, values: &[&str]) -> bool {
+    env::var(name).is_ok_and(|value| values.contains(&value.as_str()))
+}
+
+fn kitty_env() -> bool {
+    env::var_os("KITTY_WINDOW_ID").is_some()
+        || env_is("TERM", &["xterm-kitty", "xterm-ghostty"])
+        || env_is("TERM_PROGRAM", &["ghostty"])
+}
+
+// The iTerm image protocol can not be queried, so it is recognised by the terminal's name.
+fn iterm_env() -> bool {
+    env_is("TERM_PROGRAM", &["iTerm.app", "WezTerm"]) || env_is("LC_TERMINAL", &["iTerm2"])
+}
+
+#[cfg(unix)]
+fn query_terminal() -> Option<String> {
+    use std::fs::OpenOptions;
+
+    let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    crossterm::terminal::enable_raw_mode().ok()?;
+    let answers = exchange_queries(&mut tty);
+    crossterm::terminal::disable_raw_mode().ok()?;
+    answers
+}
+
+// Reads answers until DA1 is answered, or the terminal stays silent for too long.
+#[cfg(unix)]
+fn exchange_queries(tty: &mut std::fs::File) -> Option<String> {
+    use std::io::Read;
+    use std::io::Write;
+    use std::time::Instant;
+
+    use rustix::event::PollFd;
+    use rustix::event::PollFlags;
+    use rustix::event::Timespec;
+    use rustix::event::poll;
+
+    tty.write_all(
+        format!("{KITTY_QUERY}{CELL_SIZE_QUERY}{BACKGROUND_QUERY}{DA1_QUERY}").as_bytes(),
+    )
+    .ok()?;
+    tty.flush().ok()?;
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut answers = Vec::new();
+    let mut buf = [0; 256];
+    while da1_attributes(&String::from_utf8_lossy(&answers)).is_none() {
+        let timeout =
+            Timespec::try_from(deadline.saturating_duration_since(Instant::now())).ok()?;
+        let ready = poll(&mut [PollFd::new(&*tty, PollFlags::IN)], Some(&timeout)).ok()?;
+        if ready == 0 {
+            break;
+        }
+        match tty.read(&mut buf).ok()? {
+            0 => break,
+            n => answers.extend_from_slice(&buf[..n]),
+        }
+    }
+    Some(String::from_utf8_lossy(&answers).into_owned())
+}
+
+#[cfg(not(unix))]
+fn query_terminal() -> Option<String> {
+    None
+}
+
+// Picks the best image protocol the terminal supports, kitty and iTerm show images as they are,
+// while sixel reduces them to 256 colors.
+// Without any, cards are shown as styled text, or as plain text when the output is not a terminal.
+pub fn probe_terminal() -> TerminalProbe {
+    if !std::io::stdout().is_terminal() {
+        return TerminalProbe { format: OutputFormat::Clean, cell_size: None, background: None };
+    }
+    let answers = query_terminal().unwrap_or_default();
+    let sixel = da1_attributes(&answers).is_some_and(|attributes| attributes.contains(&4));
+    let format = if answers.contains("\x1b_Gi=31;OK") || kitty_env() {
+        OutputFormat::Kitty
+    } else if iterm_env() {
+        OutputFormat::ITerm
+    } else if sixel {
+        OutputFormat::Sixel
+    } else {
+        OutputFormat::Ansi
+    };
+    TerminalProbe {
+        format,
+        cell_size: reported_cell_size(&answers).or_else(window_cell_size),
+        background: reported_background(&answers),
+    }
+}
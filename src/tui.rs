@@ -0,0 +1,557 @@
+use std::io::Stdout;
+use std::io::Write;
+use std::io::stdout;
+use std::panic;
+use std::sync::Once;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use chrono::TimeDelta;
+use crossterm::QueueableCommand;
+use crossterm::cursor::Hide;
+use crossterm::cursor::MoveTo;
+use crossterm::cursor::Show;
+use crossterm::event::Event;
+use crossterm::event::KeyCode;
+use crossterm::event::KeyEvent;
+use crossterm::event::KeyEventKind;
+use crossterm::event::{self};
+use crossterm::style::Attribute;
+use crossterm::style::Print;
+use crossterm::style::SetAttribute;
+use crossterm::terminal::Clear;
+use crossterm::terminal::ClearType;
+use crossterm::terminal::EnterAlternateScreen;
+use crossterm::terminal::LeaveAlternateScreen;
+use crossterm::terminal::disable_raw_mode;
+use crossterm::terminal::enable_raw_mode;
+use unicode_width::UnicodeWidthChar;
+use unicode_width::UnicodeWidthStr;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::inline_image::KITTY_DELETE_IMAGES;
+use crate::output::RenderedCard;
+use crate::settings::OutputFormat;
+use crate::terminal::PageTurn;
+use crate::terminal::Paged;
+use crate::terminal::Pages;
+use crate::terminal::PreReviewResponse;
+use crate::terminal::REVIEW_QUESTION;
+use crate::terminal::ReviewResponse;
+use crate::terminal::SESSION_END_INSTRUCTIONS;
+use crate::terminal::SessionEndResponse;
+use crate::terminal::grab_term_size;
+use crate::terminal::is_escape_request;
+use crate::terminal::prereview_answer;
+use crate::terminal::prereview_instructions;
+use crate::terminal::review_answer;
+use crate::terminal::review_instructions;
+use crate::terminal::session_end_answer;
+use crate::types::CardMetadata;
+
+// The progress line and the reviewing phrase, see `build_typst_frontmatter`.
+const HEADER_ROWS: u16 = 2;
+// Wide enough for the longest label and a date.
+const DETAILS_COLUMNS: u16 = 36;
+const DETAILS_LABEL_COLUMNS: usize = 15;
+// How often the elapsed time in the header is brought up to date.
+const TICK: Duration = Duration::from_secs(1);
+
+// Whether the terminal is in the alternate screen and raw mode, for the panic hook to restore.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+
+fn restore_terminal() {
+    let mut stdout = stdout();
+    let _ = stdout.queue(SetAttribute(Attribute::Reset));
+    let _ = stdout.queue(Show);
+    let _ = stdout.queue(LeaveAlternateScreen);
+    let _ = stdout.flush();
+    let _ = disable_raw_mode();
+}
+
+// The panic message would be lost with the alternate screen,
+// so the terminal is restored before it is printed.
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if ACTIVE.swap(false, Ordering::SeqCst) {
+                restore_terminal();
+            }
+            previous(info);
+        }));
+    });
+}
+
+// Where the review session is at, shown in the header.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    // Counting from 1.
+    pub current: usize,
+    pub total: usize,
+    // Including the current card and cards held back by daily limits.
+    pub due_left: usize,
+}
+
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    match seconds / 3600 {
+        0 => format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+// Rounded down to the largest unit, like `4mo` or `12d`.
+fn format_interval(interval: TimeDelta) -> String {
+    let days = interval.num_days();
+    match days {
+        0 if interval.num_hours() == 0 => format!("{}m", interval.num_minutes().max(1)),
+        0 => format!("{}h", interval.num_hours()),
+        1..30 => format!("{}d", days),
+        30..365 => format!("{}mo", days / 30),
+        _ => format!("{:.1}y", days as f64 / 365.0),
+    }
+}
+
+fn format_reviewing_phrase(cm: &CardMetadata) -> String {
+    match cm.card_ref.serial_num {
+        Some(serial_num) => format!(
+            "Reviewing card with serial number {} from {}",
+            serial_num,
+            cm.card_ref.source_path.display()
+        ),
+        None => format!(
+            "Reviewing card with prompt fingerprint {} from {}",
+            cm.card_ref.prompt_fingerprint,
+            cm.card_ref.source_path.display()
+        ),
+    }
+}
+
+// Where the card comes from and how it is scheduled, as shown in the side panel.
+fn card_details(cm: &CardMetadata) -> Vec<(&'static str, String)> {
+    let card_ref = &cm.card_ref;
+    let fsrs_meta = &cm.srs_meta.fsrs_meta;
+    let page = card_ref.source_path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut details = vec![
+        ("Page", page.replace("___", "/")),
+        ("CSN", card_ref.serial_num.map_or("-".to_owned(), |csn| csn.to_string())),
+        ("Fingerprint", card_ref.prompt_fingerprint.to_string()),
+    ];
+    if cm.srs_meta.suspended {
+        details.push(("Suspended", "yes".to_owned()));
+    }
+    details.extend([
+        ("", String::new()),
+        ("State", format!("{:?}", fsrs_meta.state)),
+        ("Due", fsrs_meta.due.format("%Y-%m-%d %H:%M").to_string()),
+        ("Last review", fsrs_meta.last_review.format("%Y-%m-%d %H:%M").to_string()),
+        ("Stability", fsrs_meta.stability.to_string()),
+        ("Difficulty", fsrs_meta.difficulty.to_string()),
+        ("Elapsed days", fsrs_meta.elapsed_days.to_string()),
+        ("Scheduled days", fsrs_meta.scheduled_days.to_string()),
+        ("Reps", fsrs_meta.reps.to_string()),
+        ("Lapses", fsrs_meta.lapses.to_string()),
+    ]);
+    details
+}
+
+// Cuts the text to at most `columns` cells.
+fn clip(text: &str, columns: usize) -> String {
+    let mut width = 0;
+    text.chars()
+        .take_while(|c| {
+            width += c.width().unwrap_or(0);
+            width <= columns
+        })
+        .collect()
+}
+
+// Splits the line into rows of at most `columns` cells,
+// escape sequences styling the text take no room.
+fn wrap_to_width(line: &str, columns: usize) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            let row = rows.last_mut().unwrap();
+            row.push(c);
+            let Some(next) = chars.next() else { break };
+            row.push(next);
+            // Control sequences end with a character from `@` to `~`.
+            if next == '[' {
+                for c in chars.by_ref() {
+                    row.push(c);
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        let char_width = c.width().unwrap_or(0);
+        if width + char_width > columns && width > 0 {
+            rows.push(String::new());
+            width = 0;
+        }
+        rows.last_mut().unwrap().push(c);
+        width += char_width;
+    }
+    rows
+}
+
+// How many rows the text takes once the terminal wraps it.
+fn wrapped_rows(text: &str, columns: u16) -> u16 {
+    (text.width() as u16).div_ceil(columns.max(1)).max(1)
+}
+
+// Renders the card for the given (columns, lines), see `render_card_for_terminal`.
+pub type RenderFn<'a> = &'a dyn Fn((u16, u16)) -> Result<RenderedCard>;
+
+// What is on screen for one side of the card.
+struct CardScreen<'a> {
+    cm: &'a CardMetadata,
+    rendered: RenderedCard,
+    // Images are rendered again when the room for them changes.
+    render: RenderFn<'a>,
+    // Shown above the key hints.
+    question: Option<String>,
+    instructions: fn(Pages) -> String,
+}
+
+// The card pane of text cards is filled a screenful at a time,
+// image cards come split into pages already.
+enum CardPages {
+    Text(Vec<Vec<String>>),
+    Image,
+}
+
+// The full-screen review session: a header with the progress, the card pane,
+// a footer with the key hints and a side panel with the card details on demand.
+// The terminal is taken over when the first card is shown and restored when dropped.
+pub struct ReviewScreen {
+    session_start: Instant,
+    progress: Progress,
+    show_details: bool,
+    // Shown above the key hints until the next key is pressed.
+    notice: Option<String>,
+    // Kitty images stay on screen until they are deleted.
+    kitty_images_shown: bool,
+}
+
+impl ReviewScreen {
+    pub fn new(session_start: Instant) -> Self {
+        ReviewScreen {
+            session_start,
+            progress: Progress::default(),
+            show_details: false,
+            notice: None,
+            kitty_images_shown: false,
+        }
+    }
+
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    pub fn set_notice(&mut self, notice: impl Into<String>) {
+        self.notice = Some(notice.into());
+    }
+
+    fn enter(&self) -> Result<()> {
+        if ACTIVE.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        install_panic_hook();
+        enable_raw_mode()?;
+        ACTIVE.store(true, Ordering::SeqCst);
+        let mut stdout = stdout();
+        stdout.queue(EnterAlternateScreen)?;
+        stdout.queue(Hide)?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    // The room left for the card, next to the details when they are shown.
+    pub fn card_size(&self) -> (u16, u16) {
+        let (columns, rows) = grab_term_size();
+        (columns - self.details_columns(columns), rows)
+    }
+
+    fn details_columns(&self, columns: u16) -> u16 {
+        if self.show_details { DETAILS_COLUMNS.min(columns) } else { 0 }
+    }
+
+    pub fn wait_for_prereview(
+        &mut self,
+        cm: &CardMetadata,
+        prompt: RenderedCard,
+        render: RenderFn,
+    ) -> Result<PreReviewResponse> {
+        let screen = CardScreen {
+            cm,
+            rendered: prompt,
+            render,
+            question: None,
+            instructions: prereview_instructions,
+        };
+        self.show_until_answered(screen, prereview_answer)
+    }
+
+    // `intervals` is how long until the card is due again, for each answer in key order.
+    pub fn wait_for_review(
+        &mut self,
+        cm: &CardMetadata,
+        whole: RenderedCard,
+        render: RenderFn,
+        intervals: &[TimeDelta],
+    ) -> Result<ReviewResponse> {
+        let due_in = intervals
+            .iter()
+            .enumerate()
+            .map(|(i, interval)| format!("{}: {}", i + 1, format_interval(*interval)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let screen = CardScreen {
+            cm,
+            rendered: whole,
+            render,
+            question: Some(format!("{} Due in {}", REVIEW_QUESTION, due_in)),
+            instructions: review_instructions,
+        };
+        self.show_until_answered(screen, review_answer)
+    }
+
+    // Shows how the session ended and waits for it to be either finished
+    // or resumed by undoing the last answer.
+    pub fn wait_for_session_end(&mut self, message: &str) -> Result<SessionEndResponse> {
+        self.enter()?;
+        loop {
+            self.draw_session_end(message)?;
+            loop {
+                if !event::poll(TICK)? {
+                    let mut stdout = stdout();
+                    self.queue_progress(&mut stdout, grab_term_size().0)?;
+                    stdout.flush()?;
+                    continue;
+                }
+                match event::read()? {
+                    Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                        return Ok(session_end_answer(&key_event));
+                    }
+                    Event::Resize(_, _) => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn show_until_answered<T>(
+        &mut self,
+        mut screen: CardScreen,
+        answer: fn(Pages, &KeyEvent) -> Option<Paged<T>>,
+    ) -> Result<T> {
+        self.enter()?;
+        let mut current = 0;
+        loop {
+            let pages = self.draw(&mut screen, current)?;
+            current = pages.current;
+            loop {
+                if !event::poll(TICK)? {
+                    let mut stdout = stdout();
+                    self.queue_progress(&mut stdout, grab_term_size().0)?;
+                    stdout.flush()?;
+                    continue;
+                }
+                match event::read()? {
+                    Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                        if is_escape_request(&key_event) {
+                            return Err(Error::NopeOut);
+                        }
+                        self.notice = None;
+                        if key_event.code == KeyCode::Char('i') {
+                            self.show_details = !self.show_details;
+                            break;
+                        }
+                        match answer(pages, &key_event) {
+                            Some(Paged::Answer(answer)) => return Ok(answer),
+                            Some(Paged::Turn(PageTurn::Previous)) => {
+                                current = current.saturating_sub(1);
+                                break;
+                            }
+                            Some(Paged::Turn(PageTurn::Next)) => {
+                                current = (current + 1).min(pages.count - 1);
+                                break;
+                            }
+                            None => {}
+                        }
+                    }
+                    Event::Resize(_, _) => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Draws the whole screen, returns the page shown, which is kept within the pages there are.
+    fn draw(&mut self, screen: &mut CardScreen, current: usize) -> Result<Pages> {
+        let (columns, rows) = grab_term_size();
+        let details_columns = self.details_columns(columns);
+        let card_columns = columns - details_columns;
+        // Images are laid out for the room left next to the details, which changes on resize.
+        if screen.rendered.format().is_image()
+            && screen.rendered.term_size() != (card_columns, rows)
+        {
+            screen.rendered = (screen.render)((card_columns, rows))?;
+        }
+
+        // The footer is laid out for the page hints, even when the card fits in one page.
+        let many_pages = Pages { current: 0, count: 2 };
+        let notice_rows = self.notice.as_deref().map_or(0, |n| wrapped_rows(n, columns));
+        let question_rows = screen.question.as_deref().map_or(0, |q| wrapped_rows(q, columns));
+        let footer_rows =
+            notice_rows + question_rows + wrapped_rows(&(screen.instructions)(many_pages), columns);
+        let pane_rows = rows.saturating_sub(HEADER_ROWS + footer_rows).max(1);
+
+        let card_pages = match screen.rendered.format().is_image() {
+            true => CardPages::Image,
+            false => {
+                let text =
+                    String::from_utf8_lossy(&screen.rendered.page(0, card_columns)).into_owned();
+                let lines: Vec<String> = text
+                    .lines()
+                    .flat_map(|line| wrap_to_width(line, card_columns.max(1).into()))
+                    .collect();
+                CardPages::Text(lines.chunks(pane_rows.into()).map(<[String]>::to_vec).collect())
+            }
+        };
+        let count = match &card_pages {
+            CardPages::Text(text_pages) => text_pages.len(),
+            CardPages::Image => screen.rendered.page_count(),
+        }
+        .max(1);
+        let pages = Pages { current: current.min(count - 1), count };
+
+        let mut stdout = stdout();
+        self.clear(&mut stdout)?;
+        self.kitty_images_shown = matches!(screen.rendered.format(), OutputFormat::Kitty);
+
+        stdout.queue(MoveTo(0, HEADER_ROWS))?;
+        match &card_pages {
+            CardPages::Text(text_pages) => {
+                let text_page = text_pages.get(pages.current).map_or(&[][..], Vec::as_slice);
+                for (row, line) in (HEADER_ROWS..).zip(text_page) {
+                    stdout.queue(MoveTo(0, row))?;
+                    stdout.queue(Print(line))?;
+                }
+            }
+            CardPages::Image if screen.rendered.page_count() > 0 => {
+                stdout.write_all(&screen.rendered.page(pages.current, card_columns))?;
+            }
+            CardPages::Image => {}
+        }
+        stdout.queue(SetAttribute(Attribute::Reset))?;
+
+        if details_columns > 0 {
+            let details = card_details(screen.cm);
+            let text_columns = usize::from(details_columns).saturating_sub(2);
+            for (row, i) in (HEADER_ROWS..HEADER_ROWS + pane_rows).zip(0..) {
+                let line = match details.get(i) {
+                    Some((label, value)) if !label.is_empty() => {
+                        format!("{:<DETAILS_LABEL_COLUMNS$}{}", label, value)
+                    }
+                    _ => String::new(),
+                };
+                let line = clip(&line, text_columns);
+                let padding = " ".repeat(text_columns - line.width());
+                stdout.queue(MoveTo(card_columns, row))?;
+                stdout.queue(SetAttribute(Attribute::Dim))?;
+                stdout.queue(Print("│ "))?;
+                stdout.queue(SetAttribute(Attribute::Reset))?;
+                stdout.queue(Print(format!("{}{}", line, padding)))?;
+            }
+        }
+
+        stdout.queue(MoveTo(0, 1))?;
+        stdout.queue(Clear(ClearType::CurrentLine))?;
+        stdout.queue(Print(clip(&format_reviewing_phrase(screen.cm), columns.into())))?;
+        self.queue_progress(&mut stdout, columns)?;
+
+        // Hints are printed in one piece, wrapped by the terminal.
+        let mut row = rows.saturating_sub(footer_rows);
+        stdout.queue(MoveTo(0, row))?;
+        stdout.queue(Clear(ClearType::FromCursorDown))?;
+        if let Some(notice) = &self.notice {
+            stdout.queue(SetAttribute(Attribute::Bold))?;
+            stdout.queue(Print(notice))?;
+            stdout.queue(SetAttribute(Attribute::Reset))?;
+            row += notice_rows;
+            stdout.queue(MoveTo(0, row))?;
+        }
+        if let Some(question) = &screen.question {
+            stdout.queue(Print(question))?;
+            row += question_rows;
+            stdout.queue(MoveTo(0, row))?;
+        }
+        stdout.queue(Print((screen.instructions)(pages)))?;
+        stdout.flush()?;
+        Ok(pages)
+    }
+
+    fn draw_session_end(&mut self, message: &str) -> Result<()> {
+        let (columns, rows) = grab_term_size();
+        let mut stdout = stdout();
+        self.clear(&mut stdout)?;
+        self.kitty_images_shown = false;
+        stdout.queue(MoveTo(0, HEADER_ROWS))?;
+        stdout.queue(Print(message))?;
+        self.queue_progress(&mut stdout, columns)?;
+        stdout.queue(MoveTo(
+            0,
+            rows.saturating_sub(wrapped_rows(SESSION_END_INSTRUCTIONS, columns)),
+        ))?;
+        stdout.queue(Print(SESSION_END_INSTRUCTIONS))?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn clear(&self, stdout: &mut Stdout) -> Result<()> {
+        if self.kitty_images_shown {
+            stdout.write_all(KITTY_DELETE_IMAGES)?;
+        }
+        stdout.queue(SetAttribute(Attribute::Reset))?;
+        stdout.queue(Clear(ClearType::All))?;
+        Ok(())
+    }
+
+    fn queue_progress(&self, stdout: &mut Stdout, columns: u16) -> Result<()> {
+        let Progress { current, total, due_left } = self.progress;
+        let progress = format!(
+            " {}/{}  ·  {} due  ·  {}",
+            current,
+            total,
+            due_left,
+            format_elapsed(self.session_start.elapsed())
+        );
+        let progress = clip(&progress, columns.into());
+        let padding = " ".repeat(usize::from(columns) - progress.width());
+        stdout.queue(MoveTo(0, 0))?;
+        stdout.queue(SetAttribute(Attribute::Reverse))?;
+        stdout.queue(Print(format!("{}{}", progress, padding)))?;
+        stdout.queue(SetAttribute(Attribute::Reset))?;
+        Ok(())
+    }
+}
+
+impl Drop for ReviewScreen {
+    fn drop(&mut self) {
+        if ACTIVE.swap(false, Ordering::SeqCst) {
+            restore_terminal();
+        }
+    }
+}
@@ -1,16 +1,21 @@
+use serde::Deserialize;
 use serde::Serialize;
 use std::fmt::Debug;
 use std::path::PathBuf;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use chrono::DateTime;
 use chrono::FixedOffset;
 
 use rs_fsrs;
 
+use crate::references::LazyGraphIndex;
+
 pub type FSRSMeta = rs_fsrs::Card;
 
-#[derive(PartialEq, Clone)]
+pub type ReviewLog = rs_fsrs::ReviewLog;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Fingerprint(pub u64);
 
 impl std::fmt::Display for Fingerprint {
@@ -28,6 +33,17 @@ impl Serialize for Fingerprint {
     }
 }
 
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        let hex = raw.strip_prefix("0x").unwrap_or(&raw);
+        u64::from_str_radix(hex, 16).map(Fingerprint).map_err(serde::de::Error::custom)
+    }
+}
+
 impl From<u64> for Fingerprint {
     fn from(value: u64) -> Self {
         Fingerprint(value)
@@ -45,9 +61,9 @@ impl From<&str> for Fingerprint {
 // * I want to be able to load one card at a time and immediately store it back modified
 // * If a card has just been added it will not have a serial number assigned, so we need to use something else when writing back
 // * source_path is potentially used in lots of cards, avoid copying it
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CardRef {
-    pub source_path: Rc<PathBuf>,
+    pub source_path: Arc<PathBuf>,
     // prompt_fingerprint is XXH3 64 and will remain valid within the version of the crate,
     // but not necessarily accross.
     // The intended use is to list a set of cards, then immediately act on them one by one.
@@ -142,12 +158,18 @@ pub struct SRSMeta {
     pub logseq_srs_meta: LogseqSRSMeta,
     // fsrs_meta is optional on read, but we will always write it out
     pub fsrs_meta: FSRSMeta,
+    // Suspended cards are not offered for review until unsuspended.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub suspended: bool,
 }
 
 #[derive(Clone, Serialize)]
 pub struct CardMetadata {
     pub card_ref: CardRef,
     pub srs_meta: SRSMeta,
+    // Set by `select_card_metadata`, shared by all cards of the graph.
+    #[serde(skip)]
+    pub graph_index: Option<Arc<LazyGraphIndex>>,
 }
 
 pub struct CardBody {
@@ -162,6 +184,18 @@ pub struct Card {
     pub body: CardBody,
 }
 
+// A single entry of the review history,
+// only kept when metadata is stored in graph root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardReviewLog {
+    pub serial_num: u64,
+    pub review_log: ReviewLog,
+    // Time from showing the prompt to answering,
+    // missing in logs written before it was tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_duration_ms: Option<u64>,
+}
+
 #[derive(Clone)]
 pub enum CardId {
     Fingerprint(Fingerprint),
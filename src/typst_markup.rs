@@ -0,0 +1,766 @@
+use markdown::mdast::AlignKind;
+use markdown::mdast::Image;
+use markdown::mdast::Node;
+
+use crate::error::Error;
+use crate::error::RenderTool;
+use crate::error::Result;
+use crate::html::has_url_scheme;
+use crate::html::parse_markdown;
+
+fn unsupported(message: impl Into<String>) -> Error {
+    Error::Render { tool: RenderTool::TypstTranslator, message: message.into() }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token<'a> {
+    Command(&'a str),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Space,
+    Char(char),
+}
+
+fn tokenize(latex: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = latex.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            '\\' => match chars.peek() {
+                Some((_, next)) if next.is_ascii_alphabetic() => {
+                    let mut end = i + 1;
+                    while let Some((j, next)) = chars.peek() {
+                        if !next.is_ascii_alphabetic() {
+                            break;
+                        }
+                        end = j + 1;
+                        chars.next();
+                    }
+                    Token::Command(&latex[i + 1..end])
+                }
+                Some((j, next)) => {
+                    let end = j + next.len_utf8();
+                    let command = &latex[*j..end];
+                    chars.next();
+                    Token::Command(command)
+                }
+                None => Token::Char('\\'),
+            },
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '^' => Token::Sup,
+            '_' => Token::Sub,
+            c if c.is_whitespace() => Token::Space,
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn symbol(command: &str) -> Option<&'static str> {
+    Some(match command {
+        "alpha" => "alpha",
+        "beta" => "beta",
+        "gamma" => "gamma",
+        "delta" => "delta",
+        "epsilon" => "epsilon.alt",
+        "varepsilon" => "epsilon",
+        "zeta" => "zeta",
+        "eta" => "eta",
+        "theta" => "theta",
+        "vartheta" => "theta.alt",
+        "iota" => "iota",
+        "kappa" => "kappa",
+        "lambda" => "lambda",
+        "mu" => "mu",
+        "nu" => "nu",
+        "xi" => "xi",
+        "omicron" => "omicron",
+        "pi" => "pi",
+        "varpi" => "pi.alt",
+        "rho" => "rho",
+        "varrho" => "rho.alt",
+        "sigma" => "sigma",
+        "varsigma" => "sigma.alt",
+        "tau" => "tau",
+        "upsilon" => "upsilon",
+        "phi" => "phi.alt",
+        "varphi" => "phi",
+        "chi" => "chi",
+        "psi" => "psi",
+        "omega" => "omega",
+        "Gamma" => "Gamma",
+        "Delta" => "Delta",
+        "Theta" => "Theta",
+        "Lambda" => "Lambda",
+        "Xi" => "Xi",
+        "Pi" => "Pi",
+        "Sigma" => "Sigma",
+        "Upsilon" => "Upsilon",
+        "Phi" => "Phi",
+        "Psi" => "Psi",
+        "Omega" => "Omega",
+        "int" => "integral",
+        "iint" => "integral.double",
+        "iiint" => "integral.triple",
+        "oint" => "integral.cont",
+        "sum" => "sum",
+        "prod" => "product",
+        "coprod" => "product.co",
+        "infty" => "infinity",
+        "partial" => "diff",
+        "nabla" => "nabla",
+        "cdot" => "dot.op",
+        "times" => "times",
+        "div" => "div",
+        "pm" => "plus.minus",
+        "mp" => "minus.plus",
+        "ast" => "ast",
+        "circ" => "compose",
+        "bullet" => "bullet",
+        "leq" | "le" => "lt.eq",
+        "geq" | "ge" => "gt.eq",
+        "neq" | "ne" => "eq.not",
+        "ll" => "lt.double",
+        "gg" => "gt.double",
+        "approx" => "approx",
+        "equiv" => "equiv",
+        "sim" => "tilde.op",
+        "simeq" => "tilde.eq",
+        "cong" => "tilde.equiv",
+        "propto" => "prop",
+        "to" | "rightarrow" => "arrow.r",
+        "leftarrow" | "gets" => "arrow.l",
+        "leftrightarrow" => "arrow.l.r",
+        "Rightarrow" => "arrow.r.double",
+        "Leftarrow" => "arrow.l.double",
+        "Leftrightarrow" => "arrow.l.r.double",
+        "implies" => "arrow.r.double.long",
+        "iff" => "arrow.l.r.double.long",
+        "mapsto" => "arrow.r.bar",
+        "in" => "in",
+        "notin" => "in.not",
+        "ni" => "in.rev",
+        "subset" => "subset",
+        "subseteq" => "subset.eq",
+        "supset" => "supset",
+        "supseteq" => "supset.eq",
+        "cup" => "union",
+        "cap" => "sect",
+        "setminus" => "without",
+        "emptyset" | "varnothing" => "emptyset",
+        "forall" => "forall",
+        "exists" => "exists",
+        "neg" | "lnot" => "not",
+        "land" | "wedge" => "and",
+        "lor" | "vee" => "or",
+        "oplus" => "plus.circle",
+        "otimes" => "times.circle",
+        "perp" => "perp",
+        "parallel" => "parallel",
+        "angle" => "angle",
+        "langle" => "angle.l",
+        "rangle" => "angle.r",
+        "lfloor" => "floor.l",
+        "rfloor" => "floor.r",
+        "lceil" => "ceil.l",
+        "rceil" => "ceil.r",
+        "ldots" | "dots" => "dots.h",
+        "cdots" => "dots.c",
+        "vdots" => "dots.v",
+        "ddots" => "dots.down",
+        "prime" => "prime",
+        "ell" => "ell",
+        "hbar" => "planck.reduce",
+        "Re" => "Re",
+        "Im" => "Im",
+        "aleph" => "aleph",
+        "degree" => "degree",
+        _ => return operator(command),
+    })
+}
+
+// Operators are named the same in LaTeX and Typst.
+fn operator(command: &str) -> Option<&'static str> {
+    const OPERATORS: &[&str] = &[
+        "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh",
+        "tanh", "coth", "log", "ln", "lg", "exp", "lim", "liminf", "limsup", "max", "min", "sup",
+        "inf", "det", "gcd", "deg", "dim", "ker", "arg", "Pr", "mod",
+    ];
+    OPERATORS.iter().find(|op| **op == command).copied()
+}
+
+fn escape_string(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Converts the practical subset of LaTeX used in cards into Typst math.
+// Atoms are separated by spaces, as adjacent letters form a single identifier in Typst.
+struct LatexConverter<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> LatexConverter<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(Token::Space) {
+            self.pos += 1;
+        }
+    }
+
+    // Commas separate arguments of Typst functions, so they are escaped inside of them.
+    fn char(c: char, in_args: bool) -> Result<String> {
+        Ok(match c {
+            '(' => "\\(".to_owned(),
+            ')' => "\\)".to_owned(),
+            '/' => "\\/".to_owned(),
+            '"' => "\\\"".to_owned(),
+            '#' => "\\#".to_owned(),
+            '$' => "\\$".to_owned(),
+            ',' | ';' if in_args => format!("\\{}", c),
+            '~' => "space".to_owned(),
+            '&' => return Err(unsupported("alignment with & is not supported in LaTeX math")),
+            c => c.to_string(),
+        })
+    }
+
+    fn sequence(&mut self, in_args: bool, in_group: bool) -> Result<Vec<String>> {
+        let mut atoms: Vec<String> = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Close if in_group => break,
+                Token::Close => return Err(unsupported("unbalanced } in LaTeX math")),
+                Token::Space => self.pos += 1,
+                Token::Sup | Token::Sub => {
+                    self.pos += 1;
+                    let script = match self.argument_atoms()?.as_slice() {
+                        [atom] => atom.clone(),
+                        atoms => format!("({})", join_atoms(atoms)),
+                    };
+                    let base = atoms.pop().unwrap_or_else(|| "\"\"".to_owned());
+                    let op = if token == Token::Sup { '^' } else { '_' };
+                    atoms.push(format!("{}{}{}", base, op, script));
+                }
+                Token::Open => {
+                    self.pos += 1;
+                    let group = self.sequence(in_args, true)?;
+                    self.expect_close()?;
+                    atoms.extend(group);
+                }
+                Token::Char('\'') => {
+                    self.pos += 1;
+                    match atoms.last_mut() {
+                        Some(atom) => atom.push('\''),
+                        None => atoms.push("'".to_owned()),
+                    }
+                }
+                // Digits and decimal points of a number stay together.
+                Token::Char(c)
+                    if (c.is_ascii_digit() || c == '.')
+                        && atoms.last().is_some_and(|atom| {
+                            atom.chars().all(|a| a.is_ascii_digit() || a == '.')
+                                && atom.starts_with(|a: char| a.is_ascii_digit())
+                        }) =>
+                {
+                    self.pos += 1;
+                    atoms.last_mut().unwrap().push(c);
+                }
+                Token::Char(c) => {
+                    self.pos += 1;
+                    atoms.push(Self::char(c, in_args)?);
+                }
+                Token::Command(command) => {
+                    self.pos += 1;
+                    atoms.extend(self.command(command, in_args)?);
+                }
+            }
+        }
+        Ok(atoms)
+    }
+
+    fn expect_close(&mut self) -> Result<()> {
+        match self.next() {
+            Some(Token::Close) => Ok(()),
+            _ => Err(unsupported("unbalanced { in LaTeX math")),
+        }
+    }
+
+    // Atoms of a single token or a {group}, as taken by commands, superscripts and subscripts.
+    fn argument_atoms(&mut self) -> Result<Vec<String>> {
+        self.skip_spaces();
+        match self.next() {
+            Some(Token::Open) => {
+                let group = self.sequence(true, true)?;
+                self.expect_close()?;
+                Ok(group)
+            }
+            Some(Token::Char(c)) => Ok(vec![Self::char(c, true)?]),
+            Some(Token::Command(command)) => self.command(command, true),
+            _ => Err(unsupported("missing argument in LaTeX math")),
+        }
+    }
+
+    fn argument(&mut self) -> Result<String> {
+        Ok(join_atoms(&self.argument_atoms()?))
+    }
+
+    // The text of a {group}, as taken by \text.
+    fn text_argument(&mut self) -> Result<String> {
+        self.skip_spaces();
+        if self.next() != Some(Token::Open) {
+            return Err(unsupported("missing argument in LaTeX math"));
+        }
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(Token::Close) => return Ok(text),
+                Some(Token::Char(c)) => text.push(c),
+                Some(Token::Space) => text.push(' '),
+                Some(Token::Sup) => text.push('^'),
+                Some(Token::Sub) => text.push('_'),
+                Some(Token::Command(command)) if !command.starts_with(char::is_alphabetic) => {
+                    text.push_str(command)
+                }
+                _ => return Err(unsupported("unsupported content of \\text in LaTeX math")),
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, in_args: bool) -> Result<Vec<String>> {
+        let function = |name: &str, argument: String| vec![format!("{}({})", name, argument)];
+        Ok(match command {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                vec![format!("frac({}, {})", numerator, denominator)]
+            }
+            "sqrt" => {
+                self.skip_spaces();
+                if self.peek() == Some(Token::Char('[')) {
+                    self.pos += 1;
+                    let mut index = Vec::new();
+                    while let Some(token) = self.next() {
+                        match token {
+                            Token::Char(']') => break,
+                            Token::Char(c) => index.push(Self::char(c, true)?),
+                            Token::Command(command) => index.extend(self.command(command, true)?),
+                            Token::Space => {}
+                            _ => return Err(unsupported("unsupported index of \\sqrt")),
+                        }
+                    }
+                    let radicand = self.argument()?;
+                    vec![format!("root({}, {})", join_atoms(&index), radicand)]
+                } else {
+                    function("sqrt", self.argument()?)
+                }
+            }
+            "text" | "textrm" | "textnormal" | "mbox" => {
+                vec![format!("\"{}\"", escape_string(&self.text_argument()?))]
+            }
+            "operatorname" => vec![format!("op(\"{}\")", escape_string(&self.text_argument()?))],
+            "mathrm" => function("upright", self.argument()?),
+            "mathbf" | "boldsymbol" => function("bold", self.argument()?),
+            "mathit" => function("italic", self.argument()?),
+            "mathbb" => function("bb", self.argument()?),
+            "mathcal" => function("cal", self.argument()?),
+            "mathfrak" => function("frak", self.argument()?),
+            "mathsf" => function("sans", self.argument()?),
+            "mathtt" => function("mono", self.argument()?),
+            "hat" | "widehat" => function("hat", self.argument()?),
+            "tilde" | "widetilde" => function("tilde", self.argument()?),
+            "bar" => function("macron", self.argument()?),
+            "overline" => function("overline", self.argument()?),
+            "underline" => function("underline", self.argument()?),
+            "vec" => function("arrow", self.argument()?),
+            "dot" => function("dot", self.argument()?),
+            "ddot" => function("dot.double", self.argument()?),
+            // Delimiters are shown as they are, \left. and \right. stand for no delimiter.
+            "left" | "right" | "bigl" | "bigr" | "Bigl" | "Bigr" | "big" | "Big" | "bigg"
+            | "Bigg" => {
+                self.skip_spaces();
+                if self.peek() == Some(Token::Char('.')) {
+                    self.pos += 1;
+                }
+                vec![]
+            }
+            "limits" | "nolimits" | "displaystyle" | "textstyle" | "!" => vec![],
+            "," => vec!["thin".to_owned()],
+            ":" | ">" => vec!["med".to_owned()],
+            ";" => vec!["thick".to_owned()],
+            " " => vec!["space".to_owned()],
+            "quad" => vec!["quad".to_owned()],
+            "qquad" => vec!["wide".to_owned()],
+            "{" | "}" | "$" | "#" | "_" | "&" | "%" => vec![format!("\\{}", command)],
+            "|" => vec!["bar.v.double".to_owned()],
+            "\\" | "begin" | "end" => {
+                return Err(unsupported(format!("\\{} is not supported in LaTeX math", command)));
+            }
+            command => match symbol(command) {
+                Some(symbol) => vec![symbol.to_owned()],
+                None if command.len() == 1 && !command.starts_with(char::is_alphanumeric) => {
+                    vec![Self::char(command.chars().next().unwrap(), in_args)?]
+                }
+                None => {
+                    return Err(unsupported(format!("unsupported LaTeX command \\{}", command)));
+                }
+            },
+        })
+    }
+}
+
+// Spaces are left out inside parentheses and before the parentheses of `f(x)`.
+fn join_atoms(atoms: &[String]) -> String {
+    let mut joined = String::new();
+    for (i, atom) in atoms.iter().enumerate() {
+        if i > 0 {
+            let previous = &atoms[i - 1];
+            let tight = previous == "\\("
+                || atom == "\\)"
+                || (atom == "\\(" && previous.ends_with(|c: char| c.is_alphanumeric()));
+            if !tight {
+                joined.push(' ');
+            }
+        }
+        joined.push_str(atom);
+    }
+    joined
+}
+
+fn latex_to_typst_math(latex: &str) -> Result<String> {
+    let mut converter = LatexConverter { tokens: tokenize(latex), pos: 0 };
+    let atoms = converter.sequence(false, false)?;
+    Ok(join_atoms(&atoms))
+}
+
+// Escapes characters with a meaning in Typst markup,
+// and list, enum or heading markers when the text starts a line.
+fn escape_markup(text: &str, at_line_start: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    // The dot after them would make leading digits an enum marker like `1.`.
+    let leading_digits = text.chars().take_while(char::is_ascii_digit).count();
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' | '#' | '*' | '_' | '`' | '$' | '<' | '>' | '@' | '[' | ']' | '~' | '/' => {
+                escaped.push('\\');
+            }
+            '=' | '-' | '+' if i == 0 && at_line_start => escaped.push('\\'),
+            '.' if i == leading_digits && i > 0 && at_line_start => escaped.push('\\'),
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn indent(text: &str, first: &str, rest: &str) -> String {
+    let mut indented = String::new();
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            indented.push('\n');
+        }
+        if line.is_empty() {
+            continue;
+        }
+        indented.push_str(if i == 0 { first } else { rest });
+        indented.push_str(line);
+    }
+    indented
+}
+
+fn backtick_fence(code: &str, min: usize) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in code.chars() {
+        current = if c == '`' { current + 1 } else { 0 };
+        longest = longest.max(current);
+    }
+    "`".repeat(min.max(longest + 1))
+}
+
+// Whether the node is written as an embedded expression like `#emph[..]`,
+// which text starting with `.`, `(` or `[` would continue.
+fn is_embedded_expression(node: &Node) -> bool {
+    match node {
+        Node::Emphasis(_) | Node::Strong(_) | Node::Delete(_) | Node::Link(_) | Node::Image(_) => {
+            true
+        }
+        Node::InlineCode(code) => code.value.contains('`'),
+        Node::Text(_) | Node::InlineMath(_) | Node::Break(_) | Node::ImageReference(_) => false,
+        _ => {
+            node.children().and_then(|children| children.last()).is_some_and(is_embedded_expression)
+        }
+    }
+}
+
+// Pandoc's dimensions, where pixels and plain numbers are at 96 per inch.
+fn typst_length(value: &str) -> Result<String> {
+    let number_end = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(number_end);
+    let number: f64 =
+        number.parse().map_err(|_| unsupported(format!("image dimension {}", value)))?;
+    match unit {
+        "%" | "cm" | "mm" | "in" | "pt" | "em" => Ok(format!("{}{}", number, unit)),
+        "" | "px" => Ok(format!("{}pt", number * 0.75)),
+        _ => Err(unsupported(format!("image dimension {}", value))),
+    }
+}
+
+// Pandoc's attributes right after an image, like `![](x.png){height=50%}`.
+// Returns the Typst arguments for the width and height, and how much of the text they take,
+// or None when the text does not start with attributes.
+fn image_attributes(text: &str) -> Result<Option<(Vec<String>, usize)>> {
+    let Some(end) = text.strip_prefix('{').and_then(|rest| rest.find('}')) else {
+        return Ok(None);
+    };
+    let mut arguments = Vec::new();
+    for attribute in text[1..end + 1].split_whitespace() {
+        match attribute.split_once('=') {
+            Some((key @ ("width" | "height"), value)) => {
+                arguments.push(format!("{}: {}", key, typst_length(value.trim_matches('"'))?));
+            }
+            Some(_) => {}
+            // Identifiers and classes have no Typst counterpart.
+            None if attribute.starts_with(['#', '.']) => {}
+            None => return Ok(None),
+        }
+    }
+    Ok(Some((arguments, end + 2)))
+}
+
+struct TypstWriter<'a> {
+    markdown: &'a str,
+}
+
+impl TypstWriter<'_> {
+    // `$$x$$` inside a paragraph is display math, same as for pandoc.
+    fn is_display_math(&self, node: &Node) -> bool {
+        node.position()
+            .and_then(|position| self.markdown.get(position.start.offset..))
+            .is_some_and(|source| source.starts_with("$$"))
+    }
+
+    fn inlines(&self, node: &Node, typst: &mut String) -> Result<()> {
+        let children = node.children().map(Vec::as_slice).unwrap_or_default();
+        let mut after_expression = false;
+        // How much of the text after an image its attributes took.
+        let mut attributes_len = 0;
+        for (i, child) in children.iter().enumerate() {
+            let written = typst.len();
+            match child {
+                Node::Image(image) => {
+                    let (arguments, len) = match children.get(i + 1) {
+                        Some(Node::Text(text)) => {
+                            image_attributes(&text.value)?.unwrap_or_default()
+                        }
+                        _ => Default::default(),
+                    };
+                    self.image(image, &arguments, typst);
+                    attributes_len = len;
+                }
+                Node::Text(text) => {
+                    let text = &text.value[std::mem::take(&mut attributes_len)..];
+                    // The text would otherwise continue the expression, like `#emph[e.g].text`.
+                    if after_expression && text.starts_with(['.', '(', '[']) {
+                        typst.push(';');
+                    }
+                    self.text(text, typst);
+                }
+                _ => self.inline(child, typst)?,
+            }
+            if typst.len() != written {
+                after_expression = is_embedded_expression(child);
+            }
+        }
+        Ok(())
+    }
+
+    fn text(&self, text: &str, typst: &mut String) {
+        let at_line_start = typst.is_empty() || typst.ends_with('\n');
+        // Soft line breaks are spaces, a line could otherwise start a list.
+        typst.push_str(&escape_markup(&text.replace('\n', " "), at_line_start));
+    }
+
+    fn image(&self, image: &Image, arguments: &[String], typst: &mut String) {
+        // Typst only reads local files, images from elsewhere are linked instead.
+        if has_url_scheme(&image.url) {
+            typst.push_str(&format!("#link(\"{}\")[", escape_string(&image.url)));
+            self.text(if image.alt.is_empty() { "image" } else { &image.alt }, typst);
+            typst.push(']');
+            return;
+        }
+        typst.push_str(&format!("#box(image(\"{}\"", escape_string(&image.url)));
+        if !image.alt.is_empty() {
+            typst.push_str(&format!(", alt: \"{}\"", escape_string(&image.alt)));
+        }
+        for argument in arguments {
+            typst.push_str(&format!(", {}", argument));
+        }
+        typst.push_str("))");
+    }
+
+    fn wrapped(&self, function: &str, node: &Node, typst: &mut String) -> Result<()> {
+        typst.push_str(&format!("#{}[", function));
+        self.inlines(node, typst)?;
+        typst.push(']');
+        Ok(())
+    }
+
+    fn inline(&self, node: &Node, typst: &mut String) -> Result<()> {
+        match node {
+            Node::Text(text) => self.text(&text.value, typst),
+            Node::Emphasis(_) => self.wrapped("emph", node, typst)?,
+            Node::Strong(_) => self.wrapped("strong", node, typst)?,
+            Node::Delete(_) => self.wrapped("strike", node, typst)?,
+            Node::InlineCode(code) if !code.value.contains('`') => {
+                typst.push_str(&format!("`{}`", code.value));
+            }
+            Node::InlineCode(code) => {
+                typst.push_str(&format!("#raw(\"{}\")", escape_string(&code.value)));
+            }
+            Node::InlineMath(math) if self.is_display_math(node) => {
+                typst.push_str(&format!("$ {} $", latex_to_typst_math(&math.value)?));
+            }
+            Node::InlineMath(math) => {
+                typst.push_str(&format!("${}$", latex_to_typst_math(&math.value)?));
+            }
+            Node::Break(_) => typst.push_str("\\\n"),
+            Node::Link(link) => {
+                typst.push_str(&format!("#link(\"{}\")", escape_string(&link.url)));
+                let mut text = String::new();
+                self.inlines(node, &mut text)?;
+                // Autolinks show the URL itself.
+                if text != escape_markup(&link.url, false) {
+                    typst.push_str(&format!("[{}]", text));
+                }
+            }
+            Node::Image(image) => self.image(image, &[], typst),
+            Node::ImageReference(image) => typst.push_str(&escape_markup(&image.alt, false)),
+            // Like the HTML comments with serial numbers, raw HTML has no Typst counterpart.
+            Node::Html(_) | Node::FootnoteReference(_) => {}
+            _ => self.inlines(node, typst)?,
+        }
+        Ok(())
+    }
+
+    fn blocks<'n>(&self, nodes: impl IntoIterator<Item = &'n Node>, tight: bool) -> Result<String> {
+        let mut blocks = Vec::new();
+        for node in nodes {
+            let block = self.block(node)?;
+            if !block.is_empty() {
+                blocks.push(block);
+            }
+        }
+        Ok(blocks.join(if tight { "\n" } else { "\n\n" }))
+    }
+
+    fn block(&self, node: &Node) -> Result<String> {
+        Ok(match node {
+            Node::Root(root) => self.blocks(&root.children, false)?,
+            Node::Heading(heading) => {
+                let mut typst = format!("{} ", "=".repeat(heading.depth.into()));
+                self.inlines(node, &mut typst)?;
+                typst
+            }
+            Node::Blockquote(blockquote) => {
+                format!("#quote(block: true)[\n{}\n]", self.blocks(&blockquote.children, false)?)
+            }
+            Node::List(list) => {
+                let tight = !list.spread
+                    && list.children.iter().all(|item| match item {
+                        Node::ListItem(item) => !item.spread,
+                        _ => true,
+                    });
+                let mut items = Vec::new();
+                for (i, item) in list.children.iter().enumerate() {
+                    let marker = match list.ordered {
+                        true => format!("{}. ", list.start.unwrap_or(1) as usize + i),
+                        false => "- ".to_owned(),
+                    };
+                    let mut content = String::new();
+                    if let Node::ListItem(list_item) = item {
+                        match list_item.checked {
+                            Some(true) => content.push_str("☒ "),
+                            Some(false) => content.push_str("☐ "),
+                            None => {}
+                        }
+                    }
+                    content.push_str(&self.blocks(item.children().into_iter().flatten(), tight)?);
+                    let rest = " ".repeat(marker.len());
+                    items.push(indent(&content, &marker, &rest).trim_end().to_owned());
+                }
+                items.join(if tight { "\n" } else { "\n\n" })
+            }
+            Node::Code(code) => {
+                let fence = backtick_fence(&code.value, 3);
+                format!(
+                    "{}{}\n{}\n{}",
+                    fence,
+                    code.lang.as_deref().unwrap_or(""),
+                    code.value,
+                    fence
+                )
+            }
+            Node::Math(math) => format!("$ {} $", latex_to_typst_math(&math.value)?),
+            Node::ThematicBreak(_) => "#line(length: 100%)".to_owned(),
+            Node::Table(table) => {
+                let align: Vec<&str> = table
+                    .align
+                    .iter()
+                    .map(|align| match align {
+                        AlignKind::Left => "left",
+                        AlignKind::Right => "right",
+                        AlignKind::Center => "center",
+                        AlignKind::None => "auto",
+                    })
+                    .collect();
+                let mut typst = format!(
+                    "#table(\n  columns: {},\n  align: ({},),\n",
+                    table.align.len(),
+                    align.join(", ")
+                );
+                for (i, row) in table.children.iter().enumerate() {
+                    let mut cells = Vec::new();
+                    for cell in row.children().into_iter().flatten() {
+                        let mut content = String::new();
+                        self.inlines(cell, &mut content)?;
+                        cells.push(format!("[{}]", content));
+                    }
+                    match i {
+                        0 => typst.push_str(&format!("  table.header({}),\n", cells.join(", "))),
+                        _ => typst.push_str(&format!("  {},\n", cells.join(", "))),
+                    }
+                }
+                typst.push(')');
+                typst
+            }
+            Node::Html(_) | Node::Definition(_) | Node::FootnoteDefinition(_) => String::new(),
+            _ => {
+                let mut typst = String::new();
+                self.inlines(node, &mut typst)?;
+                typst
+            }
+        })
+    }
+}
+
+// Translates markdown into Typst markup, without the need for pandoc.
+// LaTeX math outside of the supported subset is reported as an error.
+pub fn markdown_to_typst(markdown: &str) -> Result<String> {
+    let tree = parse_markdown(markdown)?;
+    let mut typst = TypstWriter { markdown }.block(&tree)?;
+    typst.push('\n');
+    Ok(typst)
+}
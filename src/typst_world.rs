@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use chrono::Datelike;
+use typst::Library;
+use typst::LibraryExt;
+use typst::World;
+use typst::diag::FileError;
+use typst::diag::FileResult;
+use typst::diag::SourceDiagnostic;
+use typst::foundations::Bytes;
+use typst::foundations::Datetime;
+use typst::foundations::Duration;
+use typst::syntax::FileId;
+use typst::syntax::RootedPath;
+use typst::syntax::Source;
+use typst::syntax::VirtualPath;
+use typst::syntax::VirtualRoot;
+use typst::text::Font;
+use typst::text::FontBook;
+use typst::utils::LazyHash;
+use typst_layout::PagedDocument;
+use typst_render::RenderOptions;
+
+use crate::error::Error;
+use crate::error::RenderTool;
+use crate::error::Result;
+
+static LIBRARY: LazyLock<LazyHash<Library>> = LazyLock::new(|| LazyHash::new(Library::default()));
+
+// Only the fonts bundled with Typst are used, so that cards look the same on every machine.
+static FONTS: LazyLock<(LazyHash<FontBook>, Vec<Font>)> = LazyLock::new(|| {
+    let fonts: Vec<Font> =
+        typst_assets::fonts().flat_map(|data| Font::iter(Bytes::new(data))).collect();
+    (LazyHash::new(FontBook::from_fonts(&fonts)), fonts)
+});
+
+// The card is compiled as if it was a file in the graph root,
+// so `assets/...` references are read from the graph.
+const MAIN_PATH: &str = "/losrs-card.typ";
+
+fn render_error(message: impl Into<String>) -> Error {
+    Error::Render { tool: RenderTool::Typst, message: message.into() }
+}
+
+fn format_diagnostics(diagnostics: &[SourceDiagnostic]) -> String {
+    let mut message = String::new();
+    for diagnostic in diagnostics {
+        // The hints are written for the typst CLI, like adjusting `--root`, so they are left out.
+        message.push_str(&format!("error: {}\n", diagnostic.message));
+    }
+    message
+}
+
+// A Typst world with a virtual file system over the graph root,
+// where the card itself only exists in memory.
+pub struct CardWorld {
+    root: PathBuf,
+    main: Source,
+}
+
+impl CardWorld {
+    pub fn new(root: &Path, typst: String) -> Result<CardWorld> {
+        let vpath = VirtualPath::new(MAIN_PATH).map_err(|e| render_error(e.to_string()))?;
+        let main = FileId::new(RootedPath::new(VirtualRoot::Project, vpath));
+        Ok(CardWorld { root: root.to_owned(), main: Source::new(main, typst) })
+    }
+
+    fn read(&self, id: FileId) -> FileResult<Vec<u8>> {
+        let rooted = id.get();
+        if let VirtualRoot::Package(package) = rooted.root() {
+            return Err(FileError::Other(Some(
+                format!("packages are not supported, {} was requested", package).into(),
+            )));
+        }
+        let path = rooted.vpath().realize(&self.root).map_err(FileError::Realize)?;
+        if path.is_dir() {
+            return Err(FileError::IsDirectory);
+        }
+        fs::read(&path).map_err(|e| FileError::from_io(e, &path))
+    }
+
+    pub fn compile(&self) -> Result<PagedDocument> {
+        let document = typst::compile::<PagedDocument>(self)
+            .output
+            .map_err(|errors| render_error(format_diagnostics(&errors)))?;
+        // Drops cached results not used by the last few compilations.
+        typst::comemo::evict(10);
+        Ok(document)
+    }
+}
+
+// Renders every page into a PNG, at `ppi` pixels per inch.
+pub fn document_to_pngs(document: &PagedDocument, ppi: f32) -> Result<Vec<Vec<u8>>> {
+    let options =
+        RenderOptions { pixel_per_pt: (f64::from(ppi) / 72.0).into(), ..Default::default() };
+    document
+        .pages()
+        .iter()
+        .map(|page| {
+            typst_render::render(page, &options)
+                .encode_png()
+                .map_err(|e| render_error(format!("encoding png failed: {}", e)))
+        })
+        .collect()
+}
+
+impl World for CardWorld {
+    fn library(&self) -> &LazyHash<Library> {
+        &LIBRARY
+    }
+
+    fn book(&self) -> &LazyHash<FontBook> {
+        &FONTS.0
+    }
+
+    fn main(&self) -> FileId {
+        self.main.id()
+    }
+
+    fn source(&self, id: FileId) -> FileResult<Source> {
+        if id == self.main.id() {
+            return Ok(self.main.clone());
+        }
+        let text = String::from_utf8(self.read(id)?).map_err(|_| FileError::InvalidUtf8)?;
+        Ok(Source::new(id, text))
+    }
+
+    fn file(&self, id: FileId) -> FileResult<Bytes> {
+        if id == self.main.id() {
+            return Ok(Bytes::from_string(self.main.text().to_owned()));
+        }
+        self.read(id).map(Bytes::new)
+    }
+
+    fn font(&self, index: usize) -> Option<Font> {
+        FONTS.1.get(index).cloned()
+    }
+
+    fn today(&self, offset: Option<Duration>) -> Option<Datetime> {
+        let now = match offset {
+            Some(offset) => {
+                chrono::Utc::now().naive_utc() + chrono::Duration::hours(offset.hours() as i64)
+            }
+            None => chrono::Local::now().naive_local(),
+        };
+        Datetime::from_ymd(now.year(), now.month() as u8, now.day() as u8)
+    }
+}
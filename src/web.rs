@@ -0,0 +1,328 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Cursor;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use chrono::DateTime;
+use chrono::FixedOffset;
+use percent_encoding::percent_decode_str;
+use rs_fsrs::Rating;
+use serde::Deserialize;
+use tiny_http::Header;
+use tiny_http::Method;
+use tiny_http::Request;
+use tiny_http::Response;
+use tiny_http::Server;
+
+use losrs::error::Error;
+use losrs::html::STYLE;
+use losrs::html::escape_html;
+use losrs::html::html_document;
+use losrs::html::markdown_to_html;
+use losrs::output::display_markdown;
+use losrs::review;
+use losrs::review::DueCardsFilter;
+use losrs::settings::OutputSettings;
+use losrs::settings::ReviewSettings;
+use losrs::storage::StorageManager;
+use losrs::storage::find_graph_root;
+use losrs::types::CardMetadata;
+use losrs::types::CardRef;
+use losrs::types::Fingerprint;
+
+type WebResponse = Response<Cursor<Vec<u8>>>;
+
+// The review page needs no scripts, so none run even if a card smuggles one in,
+// they could answer cards from the page's own origin.
+const CONTENT_SECURITY_POLICY: &str =
+    "script-src 'none'; object-src 'none'; base-uri 'none'; form-action 'self'";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Answer {
+    Again,
+    Hard,
+    Good,
+    Easy,
+    Delay,
+}
+
+impl Answer {
+    // None for answers that postpone the card instead of reviewing it.
+    fn rating(&self) -> Option<Rating> {
+        match self {
+            Answer::Again => Some(Rating::Again),
+            Answer::Hard => Some(Rating::Hard),
+            Answer::Good => Some(Rating::Good),
+            Answer::Easy => Some(Rating::Easy),
+            Answer::Delay => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AnswerForm {
+    source_path: PathBuf,
+    prompt_fingerprint: Fingerprint,
+    serial_num: Option<u64>,
+    answer: Answer,
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(OsStr::to_str).map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("header names and values are ASCII")
+}
+
+fn html_page(title: &str, body: &str) -> String {
+    html_document(title, "<link rel=\"stylesheet\" href=\"/style.css\">\n", body)
+}
+
+fn html_response(status_code: u16, title: &str, body: &str) -> WebResponse {
+    Response::from_string(html_page(title, body))
+        .with_status_code(status_code)
+        .with_header(header("Content-Type", "text/html; charset=utf-8"))
+}
+
+fn error_response(status_code: u16, message: &str) -> WebResponse {
+    html_response(status_code, "losrs", &format!("<p>{}</p>\n", escape_html(message)))
+}
+
+fn redirect_response(location: &str) -> WebResponse {
+    Response::from_string("").with_status_code(303).with_header(header("Location", location))
+}
+
+fn hidden_input(name: &str, value: &str) -> String {
+    format!("<input type=\"hidden\" name=\"{}\" value=\"{}\">\n", name, escape_html(value))
+}
+
+fn answer_button(answer: &str, label: &str) -> String {
+    format!("<button name=\"answer\" value=\"{}\">{}</button>\n", answer, label)
+}
+
+// Answers must come from the review page itself,
+// otherwise any site open in the browser could rate cards by posting to the server.
+fn is_same_origin(request: &Request) -> bool {
+    let header_value = |name: &'static str| {
+        request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+    };
+    let Some(host) = header_value("Host") else { return false };
+    let Some(origin) = header_value("Origin").or_else(|| header_value("Referer")) else {
+        return false;
+    };
+    origin.strip_prefix("http://").and_then(|rest| rest.split('/').next()) == Some(host)
+}
+
+struct WebServer<'a> {
+    path: &'a Path,
+    assets_dir: Option<PathBuf>,
+    storage_manager: &'a mut StorageManager,
+    review_settings: &'a ReviewSettings,
+    output_settings: &'a OutputSettings,
+    at: Option<DateTime<FixedOffset>>,
+    // The card that was shown last and when, to tell how long answering took.
+    shown: Option<(CardRef, Instant)>,
+}
+
+impl WebServer<'_> {
+    fn now(&self) -> DateTime<FixedOffset> {
+        self.at.unwrap_or_else(|| chrono::offset::Local::now().fixed_offset())
+    }
+
+    fn review_page(&mut self) -> Result<WebResponse> {
+        let (card_metas, held_back) = review::select_due_cards(
+            self.path,
+            self.storage_manager,
+            self.review_settings,
+            self.now(),
+            DueCardsFilter::default(),
+        )?;
+        let Some(cm) = card_metas.first() else {
+            let message = match held_back {
+                0 => "Reviewed all cards, huzzah!".to_owned(),
+                left_due => {
+                    format!("Reached the daily limits, due cards left for later: {}", left_due)
+                }
+            };
+            return Ok(error_response(200, &message));
+        };
+        let body = self.review_page_body(cm, card_metas.len())?;
+        self.shown = Some((cm.card_ref.clone(), Instant::now()));
+        Ok(html_response(200, "losrs review", &body))
+    }
+
+    fn review_page_body(&self, cm: &CardMetadata, due: usize) -> Result<String> {
+        let card_body = self.storage_manager.load_card_body_by_ref(&cm.card_ref)?;
+        let page_name = cm.card_ref.source_path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let mut body = String::new();
+        body.push_str(&format!("<header>{} · due: {}</header>\n", escape_html(&page_name), due));
+        body.push_str(&format!(
+            "<section class=\"prompt\">{}</section>\n",
+            markdown_to_html(&display_markdown(
+                cm,
+                &card_body.prompt_text(),
+                self.output_settings
+            ))?
+        ));
+        body.push_str("<form method=\"post\" action=\"/answer\">\n");
+        body.push_str(&hidden_input("source_path", &cm.card_ref.source_path.to_string_lossy()));
+        body.push_str(&hidden_input(
+            "prompt_fingerprint",
+            &cm.card_ref.prompt_fingerprint.to_string(),
+        ));
+        if let Some(serial_num) = cm.card_ref.serial_num {
+            body.push_str(&hidden_input("serial_num", &serial_num.to_string()));
+        }
+        body.push_str("<details>\n<summary>Show the response</summary>\n");
+        body.push_str(&format!(
+            "<section class=\"response\">{}</section>\n",
+            markdown_to_html(&display_markdown(
+                cm,
+                &card_body.response_text(),
+                self.output_settings
+            ))?
+        ));
+        body.push_str(&answer_button("again", "Again"));
+        body.push_str(&answer_button("hard", "Hard"));
+        body.push_str(&answer_button("good", "Good"));
+        body.push_str(&answer_button("easy", "Easy"));
+        body.push_str("</details>\n");
+        body.push_str(&answer_button("delay", "Delay review by 1 day"));
+        body.push_str("</form>\n");
+        Ok(body)
+    }
+
+    fn answer(&mut self, form: &str) -> Result<WebResponse> {
+        let form: AnswerForm = match serde_urlencoded::from_str(form) {
+            Ok(form) => form,
+            Err(e) => return Ok(error_response(400, &format!("invalid answer: {}", e))),
+        };
+        // Only cards from the pages being reviewed can be answered.
+        if !self.storage_manager.find_page_files(self.path)?.contains(&form.source_path) {
+            return Ok(error_response(400, "the card is not from the pages being reviewed"));
+        }
+        let card_ref = CardRef {
+            source_path: Arc::new(form.source_path),
+            prompt_fingerprint: form.prompt_fingerprint,
+            serial_num: form.serial_num,
+        };
+        // The card might have been answered already, from another tab or device.
+        let cm = match self.storage_manager.load_card_meta_by_ref(&card_ref) {
+            Err(e @ Error::CardNotFound { .. }) => return Ok(error_response(404, &e.to_string())),
+            result => result?,
+        };
+
+        let at = self.now();
+        match form.answer.rating() {
+            Some(rating) => {
+                let review_duration = match self.shown.take() {
+                    Some((shown_card_ref, shown_at))
+                        if shown_card_ref.source_path == cm.card_ref.source_path
+                            && shown_card_ref.prompt_fingerprint
+                                == cm.card_ref.prompt_fingerprint =>
+                    {
+                        shown_at.elapsed()
+                    }
+                    _ => Duration::ZERO,
+                };
+                review::rate_card(&cm, at, rating, review_duration, self.storage_manager)?;
+            }
+            None => {
+                review::delay_card(&cm, at, Duration::from_hours(24), self.storage_manager)?;
+            }
+        }
+        Ok(redirect_response("/"))
+    }
+
+    fn asset(&self, name: &str) -> Result<WebResponse> {
+        let not_found = || Ok(error_response(404, "not found"));
+        let Some(assets_dir) = &self.assets_dir else { return not_found() };
+        let name = percent_decode_str(name).decode_utf8()?;
+        let name = Path::new(name.as_ref());
+        // Anything outside of assets, like the pages themselves, is not served.
+        if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+            return not_found();
+        }
+        let path = assets_dir.join(name);
+        let Ok(data) = fs::read(&path) else { return not_found() };
+        Ok(Response::from_data(data).with_header(header("Content-Type", content_type(&path))))
+    }
+
+    fn handle(&mut self, request: &mut Request) -> Result<WebResponse> {
+        let url = request.url().to_owned();
+        let url_path = url.split_once('?').map_or(url.as_str(), |(url_path, _)| url_path);
+        match (request.method(), url_path) {
+            (Method::Get, "/") => self.review_page(),
+            (Method::Get, "/style.css") => Ok(Response::from_string(STYLE)
+                .with_header(header("Content-Type", "text/css; charset=utf-8"))),
+            (Method::Post, "/answer") if !is_same_origin(request) => {
+                Ok(error_response(403, "answers are only accepted from the review page"))
+            }
+            (Method::Post, "/answer") => {
+                let mut form = String::new();
+                request.as_reader().read_to_string(&mut form)?;
+                self.answer(&form)
+            }
+            // Cards reference assets as `../assets/x.png` or `assets/x.png`,
+            // relative to the review page both point here.
+            (Method::Get, url_path) if url_path.starts_with("/assets/") => {
+                self.asset(&url_path["/assets/".len()..])
+            }
+            _ => Ok(error_response(404, "not found")),
+        }
+    }
+}
+
+pub fn bind(host: &str, port: u16) -> Result<Server> {
+    Server::http((host, port)).map_err(|e| anyhow!("could not listen on {}:{}: {}", host, port, e))
+}
+
+// Serves the review interface until the process is stopped.
+// Requests are handled one at a time, as they all go through the same StorageManager.
+pub fn serve(
+    server: Server,
+    path: &Path,
+    storage_manager: &mut StorageManager,
+    review_settings: &ReviewSettings,
+    output_settings: &OutputSettings,
+    at: Option<DateTime<FixedOffset>>,
+) -> Result<()> {
+    let assets_dir = find_graph_root(path)?.map(|graph_root| graph_root.join("assets"));
+    let mut web_server = WebServer {
+        path,
+        assets_dir,
+        storage_manager,
+        review_settings,
+        output_settings,
+        at,
+        shown: None,
+    };
+    for mut request in server.incoming_requests() {
+        let response = web_server
+            .handle(&mut request)
+            .unwrap_or_else(|e| error_response(500, &format!("{:#}", e)))
+            .with_header(header("Content-Security-Policy", CONTENT_SECURITY_POLICY));
+        // A client that went away must not stop the server for everyone else.
+        if let Err(e) = request.respond(response) {
+            eprintln!("could not respond to a request: {}", e);
+        }
+    }
+    Ok(())
+}
@@ -5,9 +5,15 @@ use rexpect::process::wait::WaitStatus;
 use rexpect::session::PtySession;
 use rexpect::session::spawn_command;
 use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
+use std::process::Stdio;
 use tempfile::TempDir;
 use test_utils::assert_eq_text;
 use test_utils::cargo_bin;
@@ -51,6 +57,8 @@ fn read_expected_exit_code(d: &Path, i: i32) -> i32 {
 enum ReviewAction {
     ExpString(String),
     Send(String),
+    // Like `Send`, with `\xNN` escapes, for answering the terminal queries.
+    SendEscaped(String),
     SendControl(char),
     Flush,
     ReadLine,
@@ -62,11 +70,12 @@ impl ReviewAction {
         let Some((review_action_name, review_action_args)) = line.split_once(",") else {
             return Err(anyhow!("no comma found for review action line: {}", line));
         };
-        let review_action_args = review_action_args.trim_end_matches(|x| x == '\n');
+        let review_action_args = review_action_args.trim_end_matches('\n');
 
         match review_action_name {
             "exp_string" => Ok(ReviewAction::ExpString(review_action_args.to_owned())),
             "send" => Ok(ReviewAction::Send(review_action_args.to_owned())),
+            "send_escaped" => Ok(ReviewAction::SendEscaped(unescape(review_action_args)?)),
             "send_control" => {
                 let c = review_action_args.chars().next().unwrap();
                 Ok(ReviewAction::SendControl(c))
@@ -82,7 +91,7 @@ impl ReviewAction {
             ReviewAction::ExpString(s) => {
                 p.exp_string(s)?;
             }
-            ReviewAction::Send(s) => {
+            ReviewAction::Send(s) | ReviewAction::SendEscaped(s) => {
                 p.send(s)?;
             }
             ReviewAction::SendControl(c) => {
@@ -103,6 +112,19 @@ impl ReviewAction {
     }
 }
 
+fn unescape(s: &str) -> Result<String> {
+    let mut rv = String::new();
+    let mut rest = s;
+    while let Some((before, after)) = rest.split_once("\\x") {
+        rv.push_str(before);
+        let code = after.get(..2).ok_or_else(|| anyhow!("truncated escape in: {}", s))?;
+        rv.push(char::from(u8::from_str_radix(code, 16)?));
+        rest = &after[2..];
+    }
+    rv.push_str(rest);
+    Ok(rv)
+}
+
 fn read_review_actions(d: &Path, i: i32) -> Vec<ReviewAction> {
     read_action_attribute(d, i, "review_actions")
         .unwrap()
@@ -131,7 +153,13 @@ fn build_args_in(action_args: &[String], graph_root: &Path) -> Vec<String> {
 struct RunLosrs {
     action_args: Vec<String>,
     action_envs: Vec<(String, String)>,
+    action_stdin: Option<String>,
     expected_stdout: String,
+    expected_exit_code: i32,
+    // Unredacted stdout is kept here, for later actions to compare theirs with.
+    stdout_path: PathBuf,
+    // Stdout of an earlier action, that this one's has to match byte for byte.
+    same_stdout_as: Option<PathBuf>,
 }
 
 impl RunLosrs {
@@ -139,7 +167,13 @@ impl RunLosrs {
         RunLosrs {
             action_args: read_action_args(d, i),
             action_envs: read_action_envs(d, i),
+            action_stdin: read_action_attribute(d, i, "action_stdin"),
             expected_stdout: read_action_attribute(d, i, "expected_stdout").unwrap(),
+            expected_exit_code: read_expected_exit_code(d, i),
+            stdout_path: d.join(format!("{:02}_actual_stdout", i)),
+            same_stdout_as: read_action_attribute(d, i, "same_stdout_as").map(|other| {
+                d.join(format!("{:02}_actual_stdout", other.trim_end().parse::<i32>().unwrap()))
+            }),
         }
     }
 
@@ -148,11 +182,39 @@ impl RunLosrs {
 
         cmd.args(build_args_in(&self.action_args, graph_root));
 
-        cmd.envs(self.action_envs.iter().map(|(k, v)| (k, v)));
+        cmd.envs(
+            self.action_envs
+                .iter()
+                .map(|(k, v)| (k, v.replace("$GRAPH_ROOT", graph_root.to_str().unwrap()))),
+        );
 
-        let output = cmd.output().unwrap();
+        let output = match &self.action_stdin {
+            Some(stdin) => {
+                let stdin = stdin.replace("$GRAPH_ROOT", graph_root.to_str().unwrap());
+                cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+                let mut child = cmd.spawn().unwrap();
+                child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+                child.wait_with_output().unwrap()
+            }
+            None => cmd.output().unwrap(),
+        };
+        fs::write(&self.stdout_path, &output.stdout)?;
         let actual_stdout = redacted_text(&String::from_utf8_lossy(&output.stdout));
         assert_eq_text!(&self.expected_stdout, &actual_stdout);
+        if let Some(other) = &self.same_stdout_as {
+            assert!(
+                fs::read(other)? == output.stdout,
+                "expected the same stdout as {}",
+                other.display()
+            );
+        }
+        assert_eq!(
+            output.status.code(),
+            Some(self.expected_exit_code),
+            "expected `losrs` to exit with exit code {}, stderr: {}",
+            self.expected_exit_code,
+            String::from_utf8_lossy(&output.stderr)
+        );
         Ok(())
     }
 }
@@ -182,7 +244,8 @@ impl RunLosrsReview {
 
         cmd.envs(self.action_envs.iter().map(|(k, v)| (k, v)));
 
-        let mut p = spawn_command(cmd, Some(1000))?;
+        // Rendering images takes a few seconds in debug builds.
+        let mut p = spawn_command(cmd, Some(10000))?;
 
         for review_action in &self.review_actions {
             review_action
@@ -190,7 +253,9 @@ impl RunLosrsReview {
                 .with_context(|| format!("while trying to execute action={:?}", review_action))?;
         }
 
-        let status = p.process.status().ok_or(anyhow!("could not get process status"))?;
+        // The process may still be printing, like a backtrace of the error, wait for it to finish.
+        p.exp_eof()?;
+        let status = p.process.wait()?;
         let exit_code = match status {
             WaitStatus::Exited(_, exit_code) => exit_code,
             _ => return Err(anyhow!("expected process to exit, got {:?}", status)),
@@ -205,23 +270,232 @@ impl RunLosrsReview {
     }
 }
 
+// Each line is a request like `GET /` or `POST /answer <form>`,
+// POST requests come from the server's own origin unless another one follows the form.
+fn read_web_requests(d: &Path, i: i32) -> Vec<String> {
+    read_action_attribute(d, i, "web_requests")
+        .unwrap()
+        .lines()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_owned())
+        .collect()
+}
+
+// Performs the request with a fresh connection,
+// returns the status line, the Location and Content-Security-Policy headers if present and the body.
+fn perform_web_request(addr: &str, request: &str) -> Result<String> {
+    let own_origin = format!("http://{}", addr);
+    let (request_line, body, origin) = match request.split_once(' ') {
+        Some(("POST", rest)) => {
+            let (url, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            let (body, origin) = rest.split_once(' ').unwrap_or((rest, &own_origin));
+            (format!("POST {}", url), body, origin)
+        }
+        _ => (request.to_owned(), "", own_origin.as_str()),
+    };
+    let mut stream = TcpStream::connect(addr)?;
+    write!(
+        stream,
+        "{} HTTP/1.1\r\nHost: {}\r\nOrigin: {}\r\nConnection: close\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        request_line,
+        addr,
+        origin,
+        body.len(),
+        body
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let mut head_lines = head.lines();
+    let mut result = format!("{}\n", head_lines.next().unwrap_or_default());
+    for line in head_lines
+        .filter(|l| l.starts_with("Location:") || l.starts_with("Content-Security-Policy:"))
+    {
+        result.push_str(&format!("{}\n", line));
+    }
+    result.push_str(body);
+    Ok(result)
+}
+
 #[derive(Debug)]
+struct RunLosrsWeb {
+    action_args: Vec<String>,
+    web_requests: Vec<String>,
+    expected_stdout: String,
+}
+
+impl RunLosrsWeb {
+    fn from_actions_dir(d: &Path, i: i32) -> Self {
+        RunLosrsWeb {
+            action_args: read_action_args(d, i),
+            web_requests: read_web_requests(d, i),
+            expected_stdout: read_action_attribute(d, i, "expected_stdout").unwrap(),
+        }
+    }
+
+    fn perform_in(&self, graph_root: &Path) -> Result<()> {
+        let mut cmd = Command::new(cargo_bin!("losrs"));
+
+        cmd.args(build_args_in(&self.action_args, graph_root));
+        cmd.stdout(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        // The server announces where it listens once it is ready.
+        let mut announcement = String::new();
+        stdout.read_line(&mut announcement)?;
+        let addr = announcement
+            .trim_end()
+            .rsplit_once("http://")
+            .map(|(_, url)| url.trim_end_matches('/').to_owned())
+            .ok_or_else(|| anyhow!("unexpected announcement: {}", announcement))?;
+
+        let mut actual_stdout = String::new();
+        for request in &self.web_requests {
+            let request = request.replace("$GRAPH_ROOT", graph_root.to_str().unwrap());
+            actual_stdout.push_str(&perform_web_request(&addr, &request)?);
+        }
+        child.kill()?;
+        child.wait()?;
+
+        assert_eq_text!(&self.expected_stdout, &redacted_text(&actual_stdout));
+        Ok(())
+    }
+}
+
+// Builds an Anki package from a directory, for packages made by Anki rather than by losrs.
+// The directory's `collection.sql` creates the collection,
+// its other files, like the `media` map and the media themselves, are packaged as they are.
+#[derive(Debug)]
+struct PackAnkiCollection {
+    action_args: Vec<String>,
+}
+
+impl PackAnkiCollection {
+    fn from_actions_dir(d: &Path, i: i32) -> Self {
+        PackAnkiCollection { action_args: read_action_args(d, i) }
+    }
+
+    fn perform_in(&self, graph_root: &Path) -> Result<()> {
+        let args = build_args_in(&self.action_args, graph_root);
+        let [.., dir, package] = args.as_slice() else {
+            return Err(anyhow!("expected a directory and a package path"));
+        };
+        let dir = Path::new(dir);
+
+        let collection = tempfile::NamedTempFile::new()?;
+        let connection = rusqlite::Connection::open(collection.path())?;
+        connection.execute_batch(&fs::read_to_string(dir.join("collection.sql"))?)?;
+        connection.close().map_err(|(_, e)| e)?;
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(package)?);
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("collection.anki2", options)?;
+        zip.write_all(&fs::read(collection.path())?)?;
+        let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == "collection.sql" {
+                continue;
+            }
+            zip.start_file(name, options)?;
+            zip.write_all(&fs::read(entry.path())?)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+// Each line is an SQL query against the package's collection.
+fn read_anki_queries(d: &Path, i: i32) -> Vec<String> {
+    read_action_attribute(d, i, "anki_queries")
+        .unwrap()
+        .lines()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_owned())
+        .collect()
+}
+
+// Opens an Anki package, prints its media map, then each query and its rows,
+// with columns separated by ` | ` and the field separator shown as `<1f>`.
+#[derive(Debug)]
+struct ReadAnkiPackage {
+    package: String,
+    anki_queries: Vec<String>,
+    expected_stdout: String,
+}
+
+impl ReadAnkiPackage {
+    fn from_actions_dir(d: &Path, i: i32) -> Self {
+        ReadAnkiPackage {
+            package: read_action_attribute(d, i, "action_args").unwrap().trim_end().to_owned(),
+            anki_queries: read_anki_queries(d, i),
+            expected_stdout: read_action_attribute(d, i, "expected_stdout").unwrap(),
+        }
+    }
+
+    fn perform_in(&self, graph_root: &Path) -> Result<()> {
+        let package = self.package.replace("$GRAPH_ROOT", graph_root.to_str().unwrap());
+        let mut archive = zip::ZipArchive::new(fs::File::open(package)?)?;
+
+        let mut actual_stdout = String::new();
+        archive.by_name("media")?.read_to_string(&mut actual_stdout)?;
+        actual_stdout.push('\n');
+
+        let mut collection = tempfile::NamedTempFile::new()?;
+        std::io::copy(&mut archive.by_name("collection.anki2")?, &mut collection)?;
+        let connection = rusqlite::Connection::open(collection.path())?;
+        for query in &self.anki_queries {
+            actual_stdout.push_str(&format!("> {}\n", query));
+            let mut statement = connection.prepare(query)?;
+            let columns = statement.column_count();
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                let mut values = Vec::new();
+                for c in 0..columns {
+                    let value = match row.get_ref(c)? {
+                        rusqlite::types::ValueRef::Null => "NULL".to_owned(),
+                        rusqlite::types::ValueRef::Integer(n) => n.to_string(),
+                        rusqlite::types::ValueRef::Real(x) => x.to_string(),
+                        rusqlite::types::ValueRef::Text(t) => String::from_utf8_lossy(t).into(),
+                        rusqlite::types::ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+                    };
+                    values.push(value.replace('\x1f', "<1f>"));
+                }
+                actual_stdout.push_str(&format!("{}\n", values.join(" | ")));
+            }
+        }
+
+        assert_eq_text!(&self.expected_stdout, &redacted_text(&actual_stdout));
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 enum Action {
     RunLosrs(RunLosrs),
     RunLosrsReview(RunLosrsReview),
+    RunLosrsWeb(RunLosrsWeb),
+    ReadAnkiPackage(ReadAnkiPackage),
+    PackAnkiCollection(PackAnkiCollection),
 }
 
 impl Action {
     fn from_actions_dir(d: &Path, i: i32) -> Option<Self> {
-        let Some(action_name) =
-            read_action_attribute(d, i, "action_name").map(|x| x.trim_end().to_owned())
-        else {
-            return None;
-        };
+        let action_name =
+            read_action_attribute(d, i, "action_name").map(|x| x.trim_end().to_owned())?;
 
         let action: Action = match action_name.as_str() {
             "RunLosrs" => Action::RunLosrs(RunLosrs::from_actions_dir(d, i)),
             "RunLosrsReview" => Action::RunLosrsReview(RunLosrsReview::from_actions_dir(d, i)),
+            "RunLosrsWeb" => Action::RunLosrsWeb(RunLosrsWeb::from_actions_dir(d, i)),
+            "ReadAnkiPackage" => Action::ReadAnkiPackage(ReadAnkiPackage::from_actions_dir(d, i)),
+            "PackAnkiCollection" => {
+                Action::PackAnkiCollection(PackAnkiCollection::from_actions_dir(d, i))
+            }
             _ => panic!("Unexpected action name: {}", action_name),
         };
         Some(action)
@@ -235,6 +509,15 @@ impl Action {
             Action::RunLosrsReview(run_losrs_review) => {
                 run_losrs_review.perform_in(graph_root)?;
             }
+            Action::RunLosrsWeb(run_losrs_web) => {
+                run_losrs_web.perform_in(graph_root)?;
+            }
+            Action::ReadAnkiPackage(read_anki_package) => {
+                read_anki_package.perform_in(graph_root)?;
+            }
+            Action::PackAnkiCollection(pack_anki_collection) => {
+                pack_anki_collection.perform_in(graph_root)?;
+            }
         }
         Ok(())
     }
@@ -292,9 +575,18 @@ test_file!(show_help, "show_help.txtar");
 test_file!(show_card_is_deeply_nested, "show_card_is_deeply_nested.txtar");
 test_file!(show_card_nested_no_response, "show_card_nested_no_response.txtar");
 test_file!(show_card_no_response, "show_card_no_response.txtar");
+test_file!(show_card_with_block_references, "show_card_with_block_references.txtar");
 test_file!(show_card_with_data_after_metadata, "show_card_with_data_after_metadata.txtar");
 test_file!(show_card_with_unicode_prompt, "show_card_with_unicode_prompt.txtar");
+test_file!(show_card_without_logseq_artefacts, "show_card_without_logseq_artefacts.txtar");
+test_file!(show_format_auto_not_a_terminal, "show_format_auto_not_a_terminal.txtar");
 test_file!(show_format_clean, "show_format_clean.txtar");
+test_file!(show_format_ansi, "show_format_ansi.txtar");
+test_file!(show_format_html, "show_format_html.txtar");
+test_file!(show_format_html_urls, "show_format_html_urls.txtar");
+test_file!(show_format_sixel_cache_unwritable, "show_format_sixel_cache_unwritable.txtar");
+test_file!(show_format_sixel_image_urls, "show_format_sixel_image_urls.txtar");
+test_file!(show_format_sixel_pages, "show_format_sixel_pages.txtar");
 test_file!(show_format_logseq, "show_format_logseq.txtar");
 test_file!(
     show_format_logseq_card_is_deeply_nested,
@@ -313,6 +605,7 @@ test_file!(
     "show_format_logseq_card_without_metadata.txtar"
 );
 test_file!(show_format_typst, "show_format_typst.txtar");
+test_file!(show_format_typst_markup, "show_format_typst_markup.txtar");
 test_file!(show_multiple_page_files, "show_multiple_page_files.txtar");
 test_file!(show_with_fingerprint, "show_with_fingerprint.txtar");
 
@@ -324,10 +617,24 @@ test_file!(config_help, "config_help.txtar");
 test_file!(config_show, "config_show.txtar");
 test_file!(config_show_with_env_override, "config_show_with_env_override.txtar");
 
+test_file!(cache_clear, "cache_clear.txtar");
+test_file!(calendar, "calendar.txtar");
+test_file!(export_anki, "export_anki.txtar");
+test_file!(import_anki, "import_anki.txtar");
+test_file!(import_anki_collection, "import_anki_collection.txtar");
+test_file!(csv, "csv.txtar");
+
+test_file!(stats, "stats.txtar");
+test_file!(serve_stdio, "serve_stdio.txtar");
+test_file!(web, "web.txtar");
+test_file!(web_scripts, "web_scripts.txtar");
+
 test_file!(review_help, "review_help.txtar");
 
 test_file!(review_card_artificial_not_due, "review_card_artificial_not_due.txtar");
 test_file!(review_card_before_last_reviewed, "review_card_before_last_reviewed.txtar");
+test_file!(review_card_details, "review_card_details.txtar");
+test_file!(review_card_with_missing_asset, "review_card_with_missing_asset.txtar");
 test_file!(review_card_not_due, "review_card_not_due.txtar");
 test_file!(review_card_not_due_early, "review_card_not_due_early.txtar");
 test_file!(review_card_second_remembered_no, "review_card_second_remembered_no.txtar");
@@ -341,6 +648,9 @@ test_file!(
     "review_card_without_meta_remembered_yes.txtar"
 );
 test_file!(review_delay, "review_delay.txtar");
+test_file!(review_due_order, "review_due_order.txtar");
+test_file!(review_max_reviews_per_day, "review_max_reviews_per_day.txtar");
+test_file!(review_new_cards_per_day, "review_new_cards_per_day.txtar");
 test_file!(review_remembered_no, "review_remembered_no.txtar");
 test_file!(review_remembered_yes, "review_remembered_yes.txtar");
 test_file!(
@@ -351,7 +661,12 @@ test_file!(
     review_remembered_yes_csn_not_assigned_not_first,
     "review_remembered_yes_csn_not_assigned_not_first.txtar"
 );
+test_file!(review_session_limit, "review_session_limit.txtar");
+test_file!(review_suspended, "review_suspended.txtar");
 test_file!(review_two_cards_seed_0, "review_two_cards_seed_0.txtar");
+test_file!(review_sixel_details, "review_sixel_details.txtar");
+test_file!(review_terminal_probe, "review_terminal_probe.txtar");
+test_file!(review_undo, "review_undo.txtar");
 test_file!(review_two_cards_seed_100, "review_two_cards_seed_100.txtar");
 
 // TODO: add a subcommand for serial number manipulation?